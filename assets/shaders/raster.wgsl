@@ -0,0 +1,44 @@
+struct Uniforms {
+    view_proj: mat4x4<f32>,
+    light_dir: vec4<f32>,
+}
+
+struct Palette {
+    colors: array<vec4<f32>, 256>,
+}
+
+@binding(0) @group(0) var<uniform> uniforms: Uniforms;
+@binding(1) @group(0) var<uniform> palette: Palette;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) block: u32,
+    @location(3) ao: f32,
+    @location(4) chunk_origin: vec3<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) normal: vec3<f32>,
+    @location(1) @interpolate(flat) block: u32,
+    @location(2) ao: f32,
+}
+
+@vertex
+fn vs_main(in: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    out.position = uniforms.view_proj * vec4<f32>(in.position + in.chunk_origin, 1.0);
+    out.normal = in.normal;
+    out.block = in.block;
+    out.ao = in.ao;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let albedo = palette.colors[in.block].rgb;
+    let diff = max(dot(normalize(in.normal), normalize(uniforms.light_dir.xyz)), 0.0);
+    let light = (0.25 + 0.75 * diff) * mix(0.4, 1.0, in.ao);
+    return vec4<f32>(albedo * light, 1.0);
+}
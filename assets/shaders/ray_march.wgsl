@@ -1,89 +1,522 @@
 struct Uniforms {
-    camera_position: vec3<f32>,
-    screen_dimensions: vec2<f32>,
-    time: f32,
+    view_position: vec4<f32>,
+    screen_size: vec2<f32>,
+    max_steps: u32,
+    max_distance: f32,
+    min_distance: f32,
+    frame_index: u32,
+    jitter: vec2<f32>,
+    inv_view_proj: mat4x4<f32>,
+    prev_view_proj: mat4x4<f32>,
+    view_proj: mat4x4<f32>,
+    taa_enabled: u32,
+    history_blend: f32,
+    pixel_angle: f32,
+    lod_bias: f32,
 }
 
 @binding(0) @group(0) var<uniform> uniforms: Uniforms;
+@binding(1) @group(0) var output: texture_storage_2d<rgba8unorm, write>;
+// distance along the primary ray, consumed by temporal reprojection
+@binding(2) @group(0) var distance_out: texture_storage_2d<r32float, write>;
+// post-projection depth, same convention as the raster depth buffer
+@binding(3) @group(0) var depth_out: texture_storage_2d<r32float, write>;
 
-struct Output {
-    pixels: array<vec4<f32>>,
+struct Palette {
+    colors: array<vec4<f32>, 256>,
 }
-@binding(1) @group(0) var<storage, read_write> output: Output;
+@binding(4) @group(0) var<uniform> palette: Palette;
 
-const MAX_STEPS: i32 = 100;
-const MAX_DIST: f32 = 100.0;
-const SURF_DIST: f32 = 0.01;
+// window origin in chunks and its size, then one pool slot per cell
+struct Indirection {
+    origin: vec3<i32>,
+    grid_size: u32,
+    slots: array<u32>,
+}
+@binding(5) @group(0) var<storage, read> indirection: Indirection;
 
-fn sdf_sphere(p: vec3<f32>, center: vec3<f32>, radius: f32) -> f32 {
-    return length(p - center) - radius;
+// resident chunks, four block ids packed into each word. each slot holds
+// full resolution followed by its 2×, 4× and 8× mips, then the chunk's octree
+// one node per word, then its occupancy masks and distance field
+struct ChunkPool {
+    words: array<u32>,
 }
+@binding(6) @group(0) var<storage, read> pool: ChunkPool;
 
-fn sdf_box(p: vec3<f32>, center: vec3<f32>, size: vec3<f32>) -> f32 {
-    let q: vec3<f32> = abs(p - center) - size;
-    return length(max(q, vec3<f32>(0.0))) + min(max(q.x, max(q.y, q.z)), 0.0);
+// coarse stand-in for chunks evicted from the pool, one per indirection cell
+struct Fallback {
+    mask: vec2<u32>,
+    block: u32,
+    padding: u32,
+}
+struct Fallbacks {
+    cells: array<Fallback>,
 }
+@binding(7) @group(0) var<storage, read> fallbacks: Fallbacks;
 
-fn get_dist(p: vec3<f32>) -> f32 {
-    // a sphere at (0,1,6) with radius 1
-    let sphere = sdf_sphere(p, vec3<f32>(0.0, 1.0, 6.0), 1.0);
-    
-    // a box at (0,-1,6) with size (1,1,1)
-    let box = sdf_box(p, vec3<f32>(0.0, -1.0, 6.0), vec3<f32>(1.0));
-    
-    // return the minimum distance between objects
-    return min(sphere, box);
+// baked dag traced alongside the chunks, absent when size is 0. node words
+// use the octree encoding, with word 0 the root
+struct StaticScene {
+    origin: vec3<i32>,
+    size: u32,
+    words: array<u32>,
 }
+@binding(8) @group(0) var<storage, read> static_scene: StaticScene;
 
-fn ray_march(ro: vec3<f32>, rd: vec3<f32>) -> f32 {
-    var dist_origin = 0.0;
-    
-    for(var i: i32 = 0; i < MAX_STEPS; i = i + 1) {
-        let p = ro + rd * dist_origin;
-        let dist_scene = get_dist(p);
-        dist_origin = dist_origin + dist_scene;
-        
-        if (dist_origin > MAX_DIST || dist_scene < SURF_DIST) {
-            break;
+// coarse grid over a box of bricks, one brick index or EMPTY_BRICK per cell
+struct BrickGrid {
+    origin: vec3<i32>,
+    dims: vec3<u32>,
+    cells: array<u32>,
+}
+@binding(9) @group(0) var<storage, read> brick_grid: BrickGrid;
+
+// 8³ bricks, four block ids packed into each word
+struct Bricks {
+    words: array<u32>,
+}
+@binding(10) @group(0) var<storage, read> bricks: Bricks;
+
+const CHUNK_SIZE: i32 = 32;
+const SLOT_WORDS: u32 = 19180u;
+const EMPTY_SLOT: u32 = 0xffffffffu;
+const FALLBACK_SLOT: u32 = 0xfffffffeu;
+const MIP_LEVELS: u32 = 3u;
+// byte offset of each level inside a slot
+var<private> LEVEL_OFFSETS: array<u32, 4> = array<u32, 4>(0u, 32768u, 36864u, 37376u);
+// word offset of the octree inside a slot
+const OCTREE_OFFSET: u32 = 9360u;
+const OCTREE_LEAF: u32 = 0x80000000u;
+const OCTREE_MIXED: u32 = 0x40000000u;
+// word offset of the occupancy masks inside a slot: a bit per voxel, then
+// the 4³ and 8³ cell summaries
+const OCCUPANCY_OFFSET: u32 = 14041u;
+const OCCUPANCY_4: u32 = 1024u;
+const OCCUPANCY_8: u32 = 1040u;
+// word offset of the distance field inside a slot: eight 4-bit distances to a
+// word, then the metric
+const DISTANCE_OFFSET: u32 = 15083u;
+const DISTANCE_METRIC: u32 = 4096u;
+const METRIC_MANHATTAN: u32 = 1u;
+const BRICK_SIZE: i32 = 8;
+const BRICK_WORDS: u32 = 128u;
+const EMPTY_BRICK: u32 = 0xffffffffu;
+
+const SKY_COLOR: vec3<f32> = vec3<f32>(0.6, 0.7, 0.8);
+const SUN_DIR: vec3<f32> = vec3<f32>(0.4, 0.8, 0.3);
+
+struct Hit {
+    t: f32,
+    block: u32,
+    normal: vec3<f32>,
+}
+
+// indirection cell of a chunk, or -1 outside the window
+fn chunk_cell(chunk: vec3<i32>) -> i32 {
+    let grid = i32(indirection.grid_size);
+    let rel = chunk - indirection.origin;
+    if (any(rel < vec3<i32>(0)) || any(rel >= vec3<i32>(grid))) {
+        return -1;
+    }
+    let cell = ((chunk % grid) + grid) % grid;
+    return (cell.z * grid + cell.y) * grid + cell.x;
+}
+
+// block at cell `v` of mip `level`, where cells are 2^level voxels wide
+fn get_voxel(v: vec3<i32>, level: u32) -> u32 {
+    // arithmetic shift floors negative coords like div_euclid
+    let chunk = v >> vec3<u32>(5u - level);
+    let cell = chunk_cell(chunk);
+    if (cell < 0) {
+        return 0u;
+    }
+    let slot = indirection.slots[cell];
+    if (slot == EMPTY_SLOT) {
+        return 0u;
+    }
+    let size = CHUNK_SIZE >> level;
+    let local = v - chunk * size;
+
+    if (slot == FALLBACK_SLOT) {
+        let fallback = fallbacks.cells[cell];
+        let coarse = local >> vec3<u32>(3u - level);
+        let bit = u32((coarse.z * 4 + coarse.y) * 4 + coarse.x);
+        let word = select(fallback.mask.x, fallback.mask.y, bit >= 32u);
+        return select(0u, fallback.block, ((word >> (bit % 32u)) & 1u) != 0u);
+    }
+
+    let index = LEVEL_OFFSETS[level] + u32((local.z * size + local.y) * size + local.x);
+    let word = pool.words[slot * SLOT_WORDS + index / 4u];
+    return (word >> ((index % 4u) * 8u)) & 0xffu;
+}
+
+// a box of world voxels known to be air, [lo, hi). empty when lo == hi
+struct Span {
+    lo: vec3<i32>,
+    hi: vec3<i32>,
+}
+
+fn cube_span(lo: vec3<i32>, size: i32) -> Span {
+    return Span(lo, lo + size);
+}
+
+// the air box the chunk's distance field vouches for around a local voxel
+fn distance_span(slot: u32, base: vec3<i32>, local: vec3<i32>) -> Span {
+    let field = slot * SLOT_WORDS + DISTANCE_OFFSET;
+    let index = u32((local.z * CHUNK_SIZE + local.y) * CHUNK_SIZE + local.x);
+    let d = i32((pool.words[field + index / 8u] >> ((index % 8u) * 4u)) & 0xfu);
+
+    // everything within d - 1 is air. under manhattan that is a diamond, so
+    // only the cube inside it is safe
+    var r = d - 1;
+    if (pool.words[field + DISTANCE_METRIC] == METRIC_MANHATTAN) {
+        r = r / 3;
+    }
+    if (r < 1) {
+        return cube_span(base + local, 0);
+    }
+
+    // the field only knows this chunk's voxels, so stay inside it
+    let lo = max(local - r, vec3<i32>(0));
+    let hi = min(local + r + 1, vec3<i32>(CHUNK_SIZE));
+    return Span(base + lo, base + hi);
+}
+
+// the empty region around voxel `v`, from whichever structure can vouch for
+// the most: missing chunks, the occupancy summaries, the distance field near
+// surfaces, or the octree in open space
+fn empty_span(v: vec3<i32>) -> Span {
+    let chunk = v >> vec3<u32>(5u);
+    let base = chunk * CHUNK_SIZE;
+    let cell = chunk_cell(chunk);
+    if (cell < 0) {
+        return cube_span(base, CHUNK_SIZE);
+    }
+    let slot = indirection.slots[cell];
+    if (slot == EMPTY_SLOT) {
+        return cube_span(base, CHUNK_SIZE);
+    }
+    if (slot == FALLBACK_SLOT) {
+        return cube_span(v, 0);
+    }
+
+    let local = v - base;
+
+    // the occupancy summaries settle cells near surfaces without walking the octree
+    let occupancy = slot * SLOT_WORDS + OCCUPANCY_OFFSET;
+    let c8 = vec3<u32>(local >> vec3<u32>(3u));
+    let bit8 = (c8.z * 4u + c8.y) * 4u + c8.x;
+    if (((pool.words[occupancy + OCCUPANCY_8 + bit8 / 32u] >> (bit8 % 32u)) & 1u) != 0u) {
+        let c4 = vec3<u32>(local >> vec3<u32>(2u));
+        let bit4 = (c4.z * 8u + c4.y) * 8u + c4.x;
+        if (((pool.words[occupancy + OCCUPANCY_4 + bit4 / 32u] >> (bit4 % 32u)) & 1u) == 0u) {
+            return cube_span(base + vec3<i32>(c4) * 4, 4);
         }
+        return distance_span(slot, base, local);
     }
-    
-    return dist_origin;
+
+    // an empty 8³ cell lies inside an empty octree leaf at least as big
+    let root = slot * SLOT_WORDS + OCTREE_OFFSET;
+    var node = pool.words[root];
+    var corner = vec3<i32>(0);
+    var size = CHUNK_SIZE;
+    while ((node & OCTREE_LEAF) == 0u) {
+        size = size / 2;
+        let upper = local >= corner + size;
+        corner += select(vec3<i32>(0), vec3<i32>(size), upper);
+        let child = select(vec3<u32>(0u), vec3<u32>(1u, 2u, 4u), upper);
+        node = pool.words[root + node + child.x + child.y + child.z];
+    }
+
+    // air leaf: leaf bit alone, neither mixed nor holding a block
+    if (node == OCTREE_LEAF) {
+        return cube_span(base + corner, size);
+    }
+    return cube_span(v, 0);
 }
 
-fn get_normal(p: vec3<f32>) -> vec3<f32> {
-    let e = vec2<f32>(0.01, 0.0);
-    let n = vec3<f32>(
-        get_dist(p + vec3<f32>(e.x, e.y, e.y)) - get_dist(p - vec3<f32>(e.x, e.y, e.y)),
-        get_dist(p + vec3<f32>(e.y, e.x, e.y)) - get_dist(p - vec3<f32>(e.y, e.x, e.y)),
-        get_dist(p + vec3<f32>(e.y, e.y, e.x)) - get_dist(p - vec3<f32>(e.y, e.y, e.x))
+// coarsest mip whose cells are no wider than the ray's cone at distance t
+fn lod_level(t: f32) -> u32 {
+    let footprint = t * uniforms.pixel_angle / uniforms.lod_bias;
+    if (footprint < 2.0) {
+        return 0u;
+    }
+    return min(u32(floor(log2(footprint))), MIP_LEVELS);
+}
+
+// 3D-DDA through the resident voxels, one cell per step. the cone widens with
+// distance, so the walk restarts on a coarser mip whenever a cell of it fits
+// inside a pixel, taking steps as wide as those cells. regions the octree
+// knows are empty are crossed in one jump, restarting the walk behind them
+fn march_voxels(ro: vec3<f32>, rd: vec3<f32>) -> Hit {
+    var hit: Hit;
+    hit.t = uniforms.max_distance;
+    hit.block = 0u;
+    hit.normal = vec3<f32>(0.0);
+
+    let step = vec3<i32>(sign(rd));
+    var level = lod_level(0.0);
+    var cell_size = f32(1u << level);
+    var t_delta = cell_size / max(abs(rd), vec3<f32>(1e-6));
+    let start = ro / cell_size;
+    var voxel = vec3<i32>(floor(start));
+    var t_max = select(start - floor(start), 1.0 - (start - floor(start)), rd > vec3<f32>(0.0)) * t_delta;
+
+    var t = 0.0;
+    var normal = vec3<f32>(0.0);
+    for (var i: u32 = 0u; i < uniforms.max_steps && t < uniforms.max_distance; i = i + 1u) {
+        var skipped = false;
+        // skip when a known-empty box holds the current cell and reaches past it
+        let cell_lo = voxel << vec3<u32>(level);
+        let cell_hi = cell_lo + (1 << level);
+        let span = empty_span(cell_lo);
+        let covers = all(span.lo <= cell_lo) && all(span.hi >= cell_hi);
+        if (covers && any(span.hi - span.lo > cell_hi - cell_lo)) {
+            let far = select(vec3<f32>(span.lo), vec3<f32>(span.hi), rd > vec3<f32>(0.0));
+            let exits = select(vec3<f32>(1e30), (far - ro) / rd, rd != vec3<f32>(0.0));
+            if (exits.x <= exits.y && exits.x <= exits.z) {
+                t = max(t, exits.x);
+                normal = vec3<f32>(-f32(step.x), 0.0, 0.0);
+            } else if (exits.y <= exits.z) {
+                t = max(t, exits.y);
+                normal = vec3<f32>(0.0, -f32(step.y), 0.0);
+            } else {
+                t = max(t, exits.z);
+                normal = vec3<f32>(0.0, 0.0, -f32(step.z));
+            }
+            skipped = true;
+        } else {
+            let block = get_voxel(voxel, level);
+            if (block != 0u) {
+                hit.t = t;
+                hit.block = block;
+                hit.normal = normal;
+                return hit;
+            }
+
+            if (t_max.x < t_max.y && t_max.x < t_max.z) {
+                t = t_max.x;
+                t_max.x += t_delta.x;
+                voxel.x += step.x;
+                normal = vec3<f32>(-f32(step.x), 0.0, 0.0);
+            } else if (t_max.y < t_max.z) {
+                t = t_max.y;
+                t_max.y += t_delta.y;
+                voxel.y += step.y;
+                normal = vec3<f32>(0.0, -f32(step.y), 0.0);
+            } else {
+                t = t_max.z;
+                t_max.z += t_delta.z;
+                voxel.z += step.z;
+                normal = vec3<f32>(0.0, 0.0, -f32(step.z));
+            }
+        }
+
+        // lod only ever coarsens, so a ray restarts for it at most MIP_LEVELS times
+        let next = lod_level(t);
+        if (skipped || next > level) {
+            level = max(level, next);
+            cell_size = f32(1u << level);
+            t_delta = cell_size / max(abs(rd), vec3<f32>(1e-6));
+            // nudge past the face just crossed so the new cell is on the far side
+            let p = (ro + rd * (t + 1e-4)) / cell_size;
+            voxel = vec3<i32>(floor(p));
+            t_max = t + select(p - floor(p), 1.0 - (p - floor(p)), rd > vec3<f32>(0.0)) * t_delta;
+        }
+    }
+
+    return hit;
+}
+
+// leaf of the static dag holding dag-local voxel `v`
+struct DagLeaf {
+    corner: vec3<i32>,
+    size: i32,
+    block: u32,
+}
+
+fn dag_lookup(v: vec3<i32>) -> DagLeaf {
+    var word = static_scene.words[0];
+    var leaf: DagLeaf;
+    leaf.corner = vec3<i32>(0);
+    leaf.size = i32(static_scene.size);
+    while ((word & OCTREE_LEAF) == 0u) {
+        leaf.size = leaf.size / 2;
+        let upper = v >= leaf.corner + leaf.size;
+        leaf.corner += select(vec3<i32>(0), vec3<i32>(leaf.size), upper);
+        let child = select(vec3<u32>(0u), vec3<u32>(1u, 2u, 4u), upper);
+        word = static_scene.words[word + child.x + child.y + child.z];
+    }
+    leaf.block = word & 0xffu;
+    return leaf;
+}
+
+// normal of the face on the axis where `t` is largest
+fn face_normal(t: vec3<f32>, rd: vec3<f32>) -> vec3<f32> {
+    if (t.x >= t.y && t.x >= t.z) {
+        return vec3<f32>(-sign(rd.x), 0.0, 0.0);
+    } else if (t.y >= t.z) {
+        return vec3<f32>(0.0, -sign(rd.y), 0.0);
+    }
+    return vec3<f32>(0.0, 0.0, -sign(rd.z));
+}
+
+// walk the static dag leaf by leaf, restarting from the root each step
+fn march_static(ro: vec3<f32>, rd: vec3<f32>) -> Hit {
+    var hit: Hit;
+    hit.t = uniforms.max_distance;
+    hit.block = 0u;
+    hit.normal = vec3<f32>(0.0);
+    if (static_scene.size == 0u) {
+        return hit;
+    }
+
+    let inv = select(vec3<f32>(1e30), 1.0 / rd, rd != vec3<f32>(0.0));
+    let lo = vec3<f32>(static_scene.origin);
+    let t0 = (lo - ro) * inv;
+    let t1 = (lo + f32(static_scene.size) - ro) * inv;
+    let near = min(t0, t1);
+    let far = max(t0, t1);
+
+    var t = max(max(near.x, near.y), max(near.z, 0.0));
+    let exit = min(min(far.x, far.y), min(far.z, uniforms.max_distance));
+    var normal = select(face_normal(near, rd), vec3<f32>(0.0), t == 0.0);
+
+    for (var i: u32 = 0u; i < uniforms.max_steps && t < exit; i = i + 1u) {
+        let p = ro + rd * (t + 1e-4) - lo;
+        let v = clamp(vec3<i32>(floor(p)), vec3<i32>(0), vec3<i32>(i32(static_scene.size) - 1));
+        let leaf = dag_lookup(v);
+        if (leaf.block != 0u) {
+            hit.t = t;
+            hit.block = leaf.block;
+            hit.normal = normal;
+            return hit;
+        }
+
+        let corner = lo + vec3<f32>(leaf.corner);
+        let far_face = select(corner, corner + f32(leaf.size), rd > vec3<f32>(0.0));
+        let exits = select(vec3<f32>(1e30), (far_face - ro) * inv, rd != vec3<f32>(0.0));
+        t = max(min(exits.x, min(exits.y, exits.z)), t + 1e-4);
+        normal = face_normal(-exits, rd);
+    }
+
+    return hit;
+}
+
+// brick holding world voxel `v`, EMPTY_BRICK for air or outside the grid
+fn brick_at(v: vec3<i32>) -> u32 {
+    let rel = (v >> vec3<u32>(3u)) - brick_grid.origin;
+    if (any(rel < vec3<i32>(0)) || any(vec3<u32>(rel) >= brick_grid.dims)) {
+        return EMPTY_BRICK;
+    }
+    let cell = vec3<u32>(rel);
+    return brick_grid.cells[(cell.z * brick_grid.dims.y + cell.y) * brick_grid.dims.x + cell.x];
+}
+
+// two-level walk of the brickmap: empty cells are crossed in one jump,
+// occupied bricks voxel by voxel
+fn march_brickmap(ro: vec3<f32>, rd: vec3<f32>) -> Hit {
+    var hit: Hit;
+    hit.t = uniforms.max_distance;
+    hit.block = 0u;
+    hit.normal = vec3<f32>(0.0);
+    if (any(brick_grid.dims == vec3<u32>(0u))) {
+        return hit;
+    }
+
+    let inv = select(vec3<f32>(1e30), 1.0 / rd, rd != vec3<f32>(0.0));
+    let lo = vec3<f32>(brick_grid.origin * BRICK_SIZE);
+    let hi = lo + vec3<f32>(brick_grid.dims * u32(BRICK_SIZE));
+    let t0 = (lo - ro) * inv;
+    let t1 = (hi - ro) * inv;
+    let near = min(t0, t1);
+    let far = max(t0, t1);
+
+    var t = max(max(near.x, near.y), max(near.z, 0.0));
+    let exit = min(min(far.x, far.y), min(far.z, uniforms.max_distance));
+    var normal = select(face_normal(near, rd), vec3<f32>(0.0), t == 0.0);
+
+    for (var i: u32 = 0u; i < uniforms.max_steps && t < exit; i = i + 1u) {
+        let v = vec3<i32>(floor(ro + rd * (t + 1e-4)));
+        let brick = brick_at(v);
+
+        var corner = (v >> vec3<u32>(3u)) * BRICK_SIZE;
+        var size = f32(BRICK_SIZE);
+        if (brick != EMPTY_BRICK) {
+            let local = vec3<u32>(v - corner);
+            let index = (local.z * 8u + local.y) * 8u + local.x;
+            let block = (bricks.words[brick * BRICK_WORDS + index / 4u] >> ((index % 4u) * 8u)) & 0xffu;
+            if (block != 0u) {
+                hit.t = t;
+                hit.block = block;
+                hit.normal = normal;
+                return hit;
+            }
+            corner = v;
+            size = 1.0;
+        }
+
+        let lo_face = vec3<f32>(corner);
+        let far_face = select(lo_face, lo_face + size, rd > vec3<f32>(0.0));
+        let exits = select(vec3<f32>(1e30), (far_face - ro) * inv, rd != vec3<f32>(0.0));
+        t = max(min(exits.x, min(exits.y, exits.z)), t + 1e-4);
+        normal = face_normal(-exits, rd);
+    }
+
+    return hit;
+}
+
+// world-space ray direction through a (possibly jittered) pixel position
+fn camera_ray(pixel_pos: vec2<f32>, resolution: vec2<f32>) -> vec3<f32> {
+    let ndc = vec2<f32>(
+        pixel_pos.x / resolution.x * 2.0 - 1.0,
+        1.0 - pixel_pos.y / resolution.y * 2.0
     );
-    return normalize(n);
+    let far = uniforms.inv_view_proj * vec4<f32>(ndc, 1.0, 1.0);
+    return normalize(far.xyz / far.w - uniforms.view_position.xyz);
 }
 
 @compute @workgroup_size(8, 8, 1)
 fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
-    let resolution = uniforms.screen_dimensions;
-    let pixel_pos = vec2<f32>(f32(global_id.x), f32(global_id.y));
-    let uv = (pixel_pos - 0.5 * resolution) / resolution.y;
-    
-    let ro = uniforms.camera_position;
-    
-    let rd = normalize(vec3<f32>(uv.x, uv.y, 1.0));
-    
-    let d = ray_march(ro, rd);
+    let resolution = uniforms.screen_size;
+    if (f32(global_id.x) >= resolution.x || f32(global_id.y) >= resolution.y) {
+        return;
+    }
+
+    // sub-pixel jitter moves the sample around inside the pixel every frame
+    let pixel_pos = vec2<f32>(f32(global_id.x), f32(global_id.y)) + 0.5 + uniforms.jitter;
+
+    let ro = uniforms.view_position.xyz;
+    let rd = camera_ray(pixel_pos, resolution);
     
+    var hit = march_voxels(ro, rd);
+    let static_hit = march_static(ro, rd);
+    if (static_hit.t < hit.t) {
+        hit = static_hit;
+    }
+    let brick_hit = march_brickmap(ro, rd);
+    if (brick_hit.t < hit.t) {
+        hit = brick_hit;
+    }
+    let d = hit.t;
     let p = ro + rd * d;
-    let n = get_normal(p);
-    
-    let light_pos = vec3<f32>(2.0, 4.0, -3.0);
-    let l = normalize(light_pos - p);
-    let diff = max(dot(n, l), 0.0);
-    
-    var color = vec3<f32>(diff);
-    
-    color = mix(color, vec3<f32>(0.6, 0.7, 0.8), 1.0 - exp(-0.0008 * d * d));
-    
-    let index = global_id.y * u32(resolution.x) + global_id.x;
-    output.pixels[index] = vec4<f32>(color, 1.0);
+
+    var color = SKY_COLOR;
+    if (hit.block != 0u) {
+        let diff = max(dot(hit.normal, normalize(SUN_DIR)), 0.0);
+        color = palette.colors[hit.block].rgb * (0.25 + 0.75 * diff);
+        color = mix(color, SKY_COLOR, 1.0 - exp(-0.00002 * d * d));
+    }
+
+    let coords = vec2<i32>(global_id.xy);
+    textureStore(output, coords, vec4<f32>(color, 1.0));
+    textureStore(distance_out, coords, vec4<f32>(d, 0.0, 0.0, 0.0));
+
+    // misses sit on the far plane so anything rasterised draws over the sky
+    var depth = 1.0;
+    if (hit.block != 0u) {
+        let clip = uniforms.view_proj * vec4<f32>(p, 1.0);
+        depth = clamp(clip.z / clip.w, 0.0, 1.0);
+    }
+    textureStore(depth_out, coords, vec4<f32>(depth, 0.0, 0.0, 0.0));
 }
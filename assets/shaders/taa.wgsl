@@ -0,0 +1,70 @@
+struct Uniforms {
+    view_position: vec4<f32>,
+    screen_size: vec2<f32>,
+    max_steps: u32,
+    max_distance: f32,
+    min_distance: f32,
+    frame_index: u32,
+    jitter: vec2<f32>,
+    inv_view_proj: mat4x4<f32>,
+    prev_view_proj: mat4x4<f32>,
+    view_proj: mat4x4<f32>,
+    taa_enabled: u32,
+    history_blend: f32,
+}
+
+@binding(0) @group(0) var<uniform> uniforms: Uniforms;
+@binding(1) @group(0) var current: texture_2d<f32>;
+@binding(2) @group(0) var hit_distance: texture_2d<f32>;
+@binding(3) @group(0) var history: texture_2d<f32>;
+@binding(4) @group(0) var history_sampler: sampler;
+@binding(5) @group(0) var resolved: texture_storage_2d<rgba8unorm, write>;
+
+fn camera_ray(pixel_pos: vec2<f32>, resolution: vec2<f32>) -> vec3<f32> {
+    let ndc = vec2<f32>(
+        pixel_pos.x / resolution.x * 2.0 - 1.0,
+        1.0 - pixel_pos.y / resolution.y * 2.0
+    );
+    let far = uniforms.inv_view_proj * vec4<f32>(ndc, 1.0, 1.0);
+    return normalize(far.xyz / far.w - uniforms.view_position.xyz);
+}
+
+@compute @workgroup_size(8, 8, 1)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let resolution = uniforms.screen_size;
+    if (f32(global_id.x) >= resolution.x || f32(global_id.y) >= resolution.y) {
+        return;
+    }
+
+    let coords = vec2<i32>(global_id.xy);
+    let max_coords = vec2<i32>(resolution) - 1;
+    let color = textureLoad(current, coords, 0).rgb;
+
+    // neighbourhood bounds of the current frame, used to reject stale history
+    var lo = color;
+    var hi = color;
+    for (var y: i32 = -1; y <= 1; y = y + 1) {
+        for (var x: i32 = -1; x <= 1; x = x + 1) {
+            let c = textureLoad(current, clamp(coords + vec2<i32>(x, y), vec2<i32>(0), max_coords), 0).rgb;
+            lo = min(lo, c);
+            hi = max(hi, c);
+        }
+    }
+
+    // reproject the surface seen through this pixel into last frame's screen
+    let pixel_pos = vec2<f32>(global_id.xy) + 0.5;
+    let d = textureLoad(hit_distance, coords, 0).r;
+    let world_pos = uniforms.view_position.xyz + camera_ray(pixel_pos, resolution) * d;
+    let prev_clip = uniforms.prev_view_proj * vec4<f32>(world_pos, 1.0);
+    let prev_ndc = prev_clip.xy / prev_clip.w;
+    let prev_uv = vec2<f32>(prev_ndc.x * 0.5 + 0.5, 0.5 - prev_ndc.y * 0.5);
+
+    var result = color;
+    let on_screen = all(prev_uv >= vec2<f32>(0.0)) && all(prev_uv <= vec2<f32>(1.0)) && prev_clip.w > 0.0;
+    if (uniforms.frame_index > 0u && on_screen) {
+        let prev = textureSampleLevel(history, history_sampler, prev_uv, 0.0).rgb;
+        result = mix(clamp(prev, lo, hi), color, uniforms.history_blend);
+    }
+
+    textureStore(resolved, coords, vec4<f32>(result, 1.0));
+}
@@ -0,0 +1,26 @@
+use honeycomb::world::{Region, SparseVoxelDag};
+
+// bake a saved world region into a sparse voxel dag:
+//   cargo run --example bake_dag -- <region file> <dag file>
+fn main() -> std::io::Result<()> {
+    let args: Vec<String> = std::env::args().collect();
+    let [_, input, output] = args.as_slice() else {
+        eprintln!("usage: bake_dag <region file> <dag file>");
+        std::process::exit(2);
+    };
+
+    let region = Region::load(input)?;
+    let dag = SparseVoxelDag::from_region(&region);
+    dag.save(output)?;
+
+    let dense = region.len() * honeycomb::world::Chunk::VOLUME;
+    let baked = dag.words().len() * 4;
+    println!(
+        "{} chunks, {} voxels per side: {} bytes dense -> {} bytes as a dag",
+        region.len(),
+        dag.size(),
+        dense,
+        baked,
+    );
+    Ok(())
+}
@@ -11,7 +11,7 @@ use winit::{
 use pollster::block_on;
 use crate::{
     window::EngineWindow,
-    renderer::Renderer,
+    renderer::{Renderer, RendererBackend},
     world::World,
     utils::{math::Vec3f, ray::Ray}
 };
@@ -39,9 +39,9 @@ pub struct InputState {
 }
 
 impl Engine {
-    pub async fn new(title: &str, width: u32, height: u32) -> Self {
+    pub async fn new(title: &str, width: u32, height: u32, backend: RendererBackend) -> Self {
         let (window, event_loop) = EngineWindow::new(title, width, height);
-        let renderer = Renderer::new(&window).await;
+        let renderer = Renderer::new(&window, backend).await;
         let seed = 12345;
         let mut world = World::new(seed);
         
@@ -151,7 +151,14 @@ impl Engine {
 
     /// current frame
     fn render(&mut self) {
-        self.renderer.render(&self.world, &self.camera);
+        let (pitch, yaw) = (self.camera.rotation.0, self.camera.rotation.1);
+        let look = Vec3f(yaw.cos() * pitch.cos(), pitch.sin(), yaw.sin() * pitch.cos());
+        let position = self.camera.position;
+
+        self.renderer.camera.position = [position.0, position.1, position.2];
+        self.renderer.camera.target = [position.0 + look.0, position.1 + look.1, position.2 + look.2];
+        self.renderer.camera.fov = self.camera.fov.to_degrees();
+        self.renderer.render(&self.world);
     }
 }
 
@@ -161,5 +168,11 @@ pub fn run_engine() {
 
 fn main() {
     println!("honeycomb, meet world. world, meet honeycomb.");
-    block_on(async { Engine::new("honeycomb", 1280, 720).await.run(); });
+    // `--raster` draws meshed chunks instead of ray marching them
+    let backend = if std::env::args().any(|arg| arg == "--raster") {
+        RendererBackend::Rasterization
+    } else {
+        RendererBackend::RayMarching
+    };
+    block_on(async { Engine::new("honeycomb", 1280, 720, backend).await.run(); });
 }
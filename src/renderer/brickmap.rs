@@ -0,0 +1,93 @@
+use wgpu::{Device, Queue};
+use wgpu::util::DeviceExt;
+use crate::world::{Brickmap, BRICK_VOLUME, EMPTY_BRICK};
+
+/// bricks a fresh brick buffer has room for
+const INITIAL_BRICKS: usize = 1024;
+
+/// gpu copy of a `Brickmap`: the grid with its header, and the bricks packed
+/// four block ids to a word. only edited bricks are re-uploaded
+pub struct GpuBrickmap {
+    grid: wgpu::Buffer,
+    bricks: wgpu::Buffer,
+    grid_words: usize,
+    capacity: usize,
+}
+
+impl GpuBrickmap {
+    /// an empty map, which the shader skips entirely
+    pub fn new(device: &Device) -> Self {
+        let grid = [0, 0, 0, 0, 0, 0, 0, EMPTY_BRICK];
+        Self {
+            grid: Self::create_grid(device, &grid),
+            bricks: Self::create_bricks(device, INITIAL_BRICKS),
+            grid_words: grid.len(),
+            capacity: INITIAL_BRICKS,
+        }
+    }
+
+    pub fn grid(&self) -> &wgpu::Buffer {
+        &self.grid
+    }
+
+    pub fn bricks(&self) -> &wgpu::Buffer {
+        &self.bricks
+    }
+
+    /// push the map's pending edits. returns (grid replaced, bricks replaced)
+    /// so bind groups holding the old buffers can be rebuilt
+    pub fn sync(&mut self, device: &Device, queue: &Queue, map: &mut Brickmap) -> (bool, bool) {
+        let (dirty, grid_dirty) = map.take_dirty();
+        let mut replaced = (false, false);
+
+        if grid_dirty {
+            let grid = map.encode_grid();
+            if grid.len() == self.grid_words {
+                queue.write_buffer(&self.grid, 0, bytemuck::cast_slice(&grid));
+            } else {
+                self.grid = Self::create_grid(device, &grid);
+                self.grid_words = grid.len();
+                replaced.0 = true;
+            }
+        }
+
+        if map.brick_capacity() > self.capacity {
+            self.capacity = map.brick_capacity().next_power_of_two();
+            self.bricks = Self::create_bricks(device, self.capacity);
+            replaced.1 = true;
+            log::debug!("brickmap storage grown to {} bricks", self.capacity);
+
+            // fresh buffer, so everything goes up rather than just the edits
+            for brick in 0..map.brick_capacity() as u32 {
+                self.write_brick(queue, map, brick);
+            }
+        } else {
+            for brick in dirty {
+                self.write_brick(queue, map, brick);
+            }
+        }
+
+        replaced
+    }
+
+    fn write_brick(&self, queue: &Queue, map: &Brickmap, brick: u32) {
+        queue.write_buffer(&self.bricks, brick as u64 * BRICK_VOLUME as u64, map.brick_bytes(brick));
+    }
+
+    fn create_grid(device: &Device, words: &[u32]) -> wgpu::Buffer {
+        device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Brickmap Grid"),
+            contents: bytemuck::cast_slice(words),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        })
+    }
+
+    fn create_bricks(device: &Device, capacity: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Brickmap Bricks"),
+            size: (capacity * BRICK_VOLUME) as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+}
@@ -0,0 +1,629 @@
+use std::borrow::Cow;
+use wgpu::Device;
+use super::profiler::GpuProfiler;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TextureHandle(usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BufferHandle(usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SamplerHandle(usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PassHandle(usize);
+
+#[derive(Debug, thiserror::Error)]
+pub enum GraphError {
+    #[error("passes form a cycle through `{0}`")]
+    Cycle(&'static str),
+    #[error("texture `{0}` is read but no pass writes it")]
+    NeverWritten(&'static str),
+}
+
+/// records draws into a graph raster pass that has no shader of its own
+pub trait RasterHook: Send + Sync {
+    fn draw(&self, pass: &mut wgpu::RenderPass<'_>);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TextureSize {
+    /// follows the surface, reallocated on resize
+    Screen,
+    Fixed(u32, u32),
+}
+
+#[derive(Debug, Clone)]
+pub struct TextureDesc {
+    pub label: &'static str,
+    pub format: wgpu::TextureFormat,
+    pub size: TextureSize,
+    /// usages on top of whatever the bindings imply, e.g. `COPY_SRC` for the final output
+    pub usage: wgpu::TextureUsages,
+    /// keep last frame's contents around for `Binding::History`
+    pub history: bool,
+}
+
+/// how a pass sees a resource, in binding-slot order
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Binding {
+    Uniform(BufferHandle),
+    /// read-only storage buffer
+    Storage(BufferHandle),
+    Sampled(TextureHandle),
+    /// last frame's contents of a history texture
+    History(TextureHandle),
+    /// write-only storage texture
+    Write(TextureHandle),
+    Sampler(SamplerHandle),
+}
+
+/// a screen-space compute pass
+#[derive(Debug, Clone)]
+pub struct ComputePassDesc {
+    pub label: &'static str,
+    pub shader: &'static str,
+    pub bindings: Vec<Binding>,
+    pub workgroup_size: (u32, u32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DepthAttachment {
+    pub texture: TextureHandle,
+    /// clear to the far plane instead of keeping the previous writer's depth
+    pub clear: bool,
+    /// depth state for the pass's own fullscreen shader, ignored by hooks
+    pub write: bool,
+    pub compare: wgpu::CompareFunction,
+}
+
+/// a render pass over graph textures. attachments are loaded, so the pass
+/// both reads and writes them
+#[derive(Debug, Clone)]
+pub struct RasterPassDesc {
+    pub label: &'static str,
+    /// fullscreen-triangle shader with `vs_main` and `fs_main`. without one the
+    /// pass only runs its hooks
+    pub shader: Option<&'static str>,
+    pub bindings: Vec<Binding>,
+    pub color: Option<TextureHandle>,
+    pub depth: Option<DepthAttachment>,
+}
+
+struct TextureResource {
+    desc: TextureDesc,
+    usage: wgpu::TextureUsages,
+    /// one version, or two for history textures
+    textures: Vec<wgpu::Texture>,
+    views: Vec<wgpu::TextureView>,
+}
+
+enum NodeKind {
+    Compute {
+        pipeline: wgpu::ComputePipeline,
+        workgroup_size: (u32, u32),
+    },
+    Raster {
+        pipeline: Option<wgpu::RenderPipeline>,
+        color: Option<TextureHandle>,
+        depth: Option<DepthAttachment>,
+        hooks: Vec<Box<dyn RasterHook>>,
+    },
+}
+
+struct PassNode {
+    label: &'static str,
+    bindings: Vec<Binding>,
+    kind: NodeKind,
+    layout: wgpu::BindGroupLayout,
+    /// indexed by frame parity
+    bind_groups: Vec<wgpu::BindGroup>,
+    enabled: bool,
+}
+
+impl PassNode {
+    /// textures this pass writes, attachments included
+    fn writes(&self) -> Vec<TextureHandle> {
+        let mut writes: Vec<_> = self.bindings
+            .iter()
+            .filter_map(|binding| match *binding {
+                Binding::Write(h) => Some(h),
+                _ => None,
+            })
+            .collect();
+        if let NodeKind::Raster { color, depth, .. } = &self.kind {
+            writes.extend(color.iter().copied());
+            writes.extend(depth.iter().map(|depth| depth.texture));
+        }
+        writes
+    }
+
+    /// textures whose current contents this pass depends on
+    fn reads(&self) -> Vec<TextureHandle> {
+        let mut reads: Vec<_> = self.bindings
+            .iter()
+            .filter_map(|binding| match *binding {
+                Binding::Sampled(h) => Some(h),
+                _ => None,
+            })
+            .collect();
+        if let NodeKind::Raster { color, depth, .. } = &self.kind {
+            reads.extend(color.iter().copied());
+            reads.extend(depth.iter().filter(|depth| !depth.clear).map(|depth| depth.texture));
+        }
+        reads
+    }
+}
+
+/// declares passes by the resources they touch, then owns the transient
+/// textures, execution order, and bind groups for them
+pub struct RenderGraph {
+    textures: Vec<TextureResource>,
+    buffers: Vec<wgpu::Buffer>,
+    samplers: Vec<wgpu::Sampler>,
+    passes: Vec<PassNode>,
+    order: Vec<usize>,
+    dimensions: (u32, u32),
+    parity: usize,
+}
+
+impl RenderGraph {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            textures: Vec::new(),
+            buffers: Vec::new(),
+            samplers: Vec::new(),
+            passes: Vec::new(),
+            order: Vec::new(),
+            dimensions: (width, height),
+            parity: 0,
+        }
+    }
+
+    /// hand a buffer owned elsewhere to the graph
+    pub fn import_buffer(&mut self, buffer: wgpu::Buffer) -> BufferHandle {
+        self.buffers.push(buffer);
+        BufferHandle(self.buffers.len() - 1)
+    }
+
+    /// swap an imported buffer, e.g. after it was grown, and rebuild the bind groups using it
+    pub fn replace_buffer(&mut self, device: &Device, handle: BufferHandle, buffer: wgpu::Buffer) {
+        self.buffers[handle.0] = buffer;
+        for index in 0..self.passes.len() {
+            let uses = self.passes[index].bindings.iter().any(|binding| {
+                matches!(binding, Binding::Uniform(h) | Binding::Storage(h) if *h == handle)
+            });
+            if uses {
+                self.rebuild_bind_groups(device, index);
+            }
+        }
+    }
+
+    /// declare a transient texture, allocated by `build`
+    pub fn create_texture(&mut self, desc: TextureDesc) -> TextureHandle {
+        self.textures.push(TextureResource {
+            usage: desc.usage,
+            desc,
+            textures: Vec::new(),
+            views: Vec::new(),
+        });
+        TextureHandle(self.textures.len() - 1)
+    }
+
+    pub fn create_sampler(&mut self, device: &Device, desc: &wgpu::SamplerDescriptor) -> SamplerHandle {
+        self.samplers.push(device.create_sampler(desc));
+        SamplerHandle(self.samplers.len() - 1)
+    }
+
+    /// compile a compute pass; its textures must already be declared
+    pub fn add_compute_pass(&mut self, device: &Device, desc: ComputePassDesc) -> PassHandle {
+        let layout = self.create_layout(device, desc.label, &desc.bindings, wgpu::ShaderStages::COMPUTE);
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some(desc.label),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some(desc.label),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(desc.shader)),
+        });
+
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some(desc.label),
+            layout: Some(&pipeline_layout),
+            module: &shader,
+            entry_point: Some("main"),
+            compilation_options: Default::default(),
+            cache: None,
+        });
+
+        self.push_pass(desc.label, desc.bindings, layout, NodeKind::Compute {
+            pipeline,
+            workgroup_size: desc.workgroup_size,
+        })
+    }
+
+    /// compile a raster pass; its textures must already be declared
+    pub fn add_raster_pass(&mut self, device: &Device, desc: RasterPassDesc) -> PassHandle {
+        if let Some(color) = desc.color {
+            self.textures[color.0].usage |= wgpu::TextureUsages::RENDER_ATTACHMENT;
+        }
+        if let Some(depth) = desc.depth {
+            self.textures[depth.texture.0].usage |= wgpu::TextureUsages::RENDER_ATTACHMENT;
+        }
+
+        let layout = self.create_layout(device, desc.label, &desc.bindings, wgpu::ShaderStages::FRAGMENT);
+
+        let pipeline = desc.shader.map(|source| {
+            let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some(desc.label),
+                bind_group_layouts: &[&layout],
+                push_constant_ranges: &[],
+            });
+
+            let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some(desc.label),
+                source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(source)),
+            });
+
+            let targets: Vec<_> = desc.color
+                .map(|color| Some(wgpu::ColorTargetState {
+                    format: self.textures[color.0].desc.format,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                }))
+                .into_iter()
+                .collect();
+
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(desc.label),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: Some("vs_main"),
+                    compilation_options: Default::default(),
+                    buffers: &[],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: Some("fs_main"),
+                    compilation_options: Default::default(),
+                    targets: &targets,
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: desc.depth.map(|depth| wgpu::DepthStencilState {
+                    format: self.textures[depth.texture.0].desc.format,
+                    depth_write_enabled: depth.write,
+                    depth_compare: depth.compare,
+                    stencil: Default::default(),
+                    bias: Default::default(),
+                }),
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+                cache: None,
+            })
+        });
+
+        self.push_pass(desc.label, desc.bindings, layout, NodeKind::Raster {
+            pipeline,
+            color: desc.color,
+            depth: desc.depth,
+            hooks: Vec::new(),
+        })
+    }
+
+    /// attach a draw callback to a raster pass
+    pub fn add_hook(&mut self, pass: PassHandle, hook: Box<dyn RasterHook>) {
+        match &mut self.passes[pass.0].kind {
+            NodeKind::Raster { hooks, .. } => hooks.push(hook),
+            NodeKind::Compute { .. } => panic!("`{}` is not a raster pass", self.passes[pass.0].label),
+        }
+    }
+
+    /// order the passes, allocate textures, and create bind groups
+    pub fn build(&mut self, device: &Device) -> Result<(), GraphError> {
+        self.order = self.sort_passes()?;
+        for index in 0..self.textures.len() {
+            self.allocate_texture(device, index);
+        }
+        for index in 0..self.passes.len() {
+            self.rebuild_bind_groups(device, index);
+        }
+        Ok(())
+    }
+
+    /// reallocate screen-sized textures and everything bound to them
+    pub fn resize(&mut self, device: &Device, width: u32, height: u32) {
+        self.dimensions = (width, height);
+        for index in 0..self.textures.len() {
+            if self.textures[index].desc.size == TextureSize::Screen {
+                self.allocate_texture(device, index);
+            }
+        }
+        for index in 0..self.passes.len() {
+            self.rebuild_bind_groups(device, index);
+        }
+    }
+
+    /// skipped passes keep their resources, they just don't run
+    pub fn set_enabled(&mut self, pass: PassHandle, enabled: bool) {
+        self.passes[pass.0].enabled = enabled;
+    }
+
+    /// this frame's version of a texture
+    pub fn texture(&self, handle: TextureHandle) -> &wgpu::Texture {
+        let resource = &self.textures[handle.0];
+        &resource.textures[self.parity % resource.textures.len()]
+    }
+
+    pub fn buffer(&self, handle: BufferHandle) -> &wgpu::Buffer {
+        &self.buffers[handle.0]
+    }
+
+    pub fn dimensions(&self) -> (u32, u32) {
+        self.dimensions
+    }
+
+    /// record every enabled pass in dependency order
+    pub fn execute(&self, encoder: &mut wgpu::CommandEncoder, mut profiler: Option<&mut GpuProfiler>) {
+        for &index in &self.order {
+            let node = &self.passes[index];
+            if !node.enabled {
+                continue;
+            }
+
+            match &node.kind {
+                NodeKind::Compute { pipeline, workgroup_size } => {
+                    let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                        label: Some(node.label),
+                        timestamp_writes: profiler.as_mut().and_then(|p| p.compute_pass(node.label)),
+                    });
+                    pass.set_pipeline(pipeline);
+                    pass.set_bind_group(0, &node.bind_groups[self.parity], &[]);
+                    pass.dispatch_workgroups(
+                        self.dimensions.0.div_ceil(workgroup_size.0),
+                        self.dimensions.1.div_ceil(workgroup_size.1),
+                        1
+                    );
+                }
+                NodeKind::Raster { pipeline, color, depth, hooks } => {
+                    let color_attachments: Vec<_> = color
+                        .map(|color| Some(wgpu::RenderPassColorAttachment {
+                            view: self.view(color),
+                            resolve_target: None,
+                            ops: wgpu::Operations {
+                                load: wgpu::LoadOp::Load,
+                                store: wgpu::StoreOp::Store,
+                            },
+                        }))
+                        .into_iter()
+                        .collect();
+
+                    let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                        label: Some(node.label),
+                        color_attachments: &color_attachments,
+                        depth_stencil_attachment: depth.map(|depth| wgpu::RenderPassDepthStencilAttachment {
+                            view: self.view(depth.texture),
+                            depth_ops: Some(wgpu::Operations {
+                                load: if depth.clear { wgpu::LoadOp::Clear(1.0) } else { wgpu::LoadOp::Load },
+                                store: wgpu::StoreOp::Store,
+                            }),
+                            stencil_ops: None,
+                        }),
+                        timestamp_writes: profiler.as_mut().and_then(|p| p.render_pass(node.label)),
+                        occlusion_query_set: None,
+                    });
+
+                    if let Some(pipeline) = pipeline {
+                        pass.set_pipeline(pipeline);
+                        pass.set_bind_group(0, &node.bind_groups[self.parity], &[]);
+                        pass.draw(0..3, 0..1);
+                    }
+                    for hook in hooks {
+                        hook.draw(&mut pass);
+                    }
+                }
+            }
+        }
+    }
+
+    /// flip history textures so this frame's writes become next frame's history
+    pub fn end_frame(&mut self) {
+        self.parity = 1 - self.parity;
+    }
+
+    fn binding_type(&self, binding: &Binding) -> wgpu::BindingType {
+        match *binding {
+            Binding::Uniform(_) => wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            Binding::Storage(_) => wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: true },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            Binding::Sampled(h) | Binding::History(h) => wgpu::BindingType::Texture {
+                sample_type: self.textures[h.0].desc.format
+                    .sample_type(None, None)
+                    .unwrap_or(wgpu::TextureSampleType::Float { filterable: false }),
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            Binding::Write(h) => wgpu::BindingType::StorageTexture {
+                access: wgpu::StorageTextureAccess::WriteOnly,
+                format: self.textures[h.0].desc.format,
+                view_dimension: wgpu::TextureViewDimension::D2,
+            },
+            Binding::Sampler(_) => wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+        }
+    }
+
+    /// a read depends on the last pass declared before it that writes the
+    /// texture, or the first writer if it was declared ahead of all of them.
+    /// history reads see last frame and add no edge
+    fn sort_passes(&self) -> Result<Vec<usize>, GraphError> {
+        let writes: Vec<_> = self.passes.iter().map(PassNode::writes).collect();
+
+        let mut dependencies = vec![Vec::new(); self.passes.len()];
+        for (index, node) in self.passes.iter().enumerate() {
+            for h in node.reads() {
+                let earlier = (0..index).rev().find(|&i| writes[i].contains(&h));
+                let writer = earlier
+                    .or_else(|| (index + 1..self.passes.len()).find(|&i| writes[i].contains(&h)));
+                match writer {
+                    Some(writer) => dependencies[index].push(writer),
+                    // attachments may be the first writer of their own texture
+                    None if writes[index].contains(&h) => (),
+                    None => return Err(GraphError::NeverWritten(self.textures[h.0].desc.label)),
+                }
+            }
+        }
+
+        let mut order = Vec::with_capacity(self.passes.len());
+        let mut placed = vec![false; self.passes.len()];
+        while order.len() < self.passes.len() {
+            let next = (0..self.passes.len())
+                .find(|&i| !placed[i] && dependencies[i].iter().all(|&d| placed[d]));
+            match next {
+                Some(i) => {
+                    placed[i] = true;
+                    order.push(i);
+                }
+                None => {
+                    let stuck = (0..self.passes.len()).find(|&i| !placed[i]).unwrap();
+                    return Err(GraphError::Cycle(self.passes[stuck].label));
+                }
+            }
+        }
+        Ok(order)
+    }
+
+    fn create_layout(
+        &mut self,
+        device: &Device,
+        label: &'static str,
+        bindings: &[Binding],
+        visibility: wgpu::ShaderStages,
+    ) -> wgpu::BindGroupLayout {
+        for binding in bindings {
+            match *binding {
+                Binding::Sampled(h) | Binding::History(h) => {
+                    self.textures[h.0].usage |= wgpu::TextureUsages::TEXTURE_BINDING;
+                }
+                Binding::Write(h) => {
+                    self.textures[h.0].usage |= wgpu::TextureUsages::STORAGE_BINDING;
+                }
+                _ => (),
+            }
+        }
+
+        let entries: Vec<_> = bindings
+            .iter()
+            .enumerate()
+            .map(|(slot, binding)| wgpu::BindGroupLayoutEntry {
+                binding: slot as u32,
+                visibility,
+                ty: self.binding_type(binding),
+                count: None,
+            })
+            .collect();
+
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some(label),
+            entries: &entries,
+        })
+    }
+
+    fn push_pass(
+        &mut self,
+        label: &'static str,
+        bindings: Vec<Binding>,
+        layout: wgpu::BindGroupLayout,
+        kind: NodeKind,
+    ) -> PassHandle {
+        self.passes.push(PassNode {
+            label,
+            bindings,
+            kind,
+            layout,
+            bind_groups: Vec::new(),
+            enabled: true,
+        });
+        PassHandle(self.passes.len() - 1)
+    }
+
+    fn view(&self, handle: TextureHandle) -> &wgpu::TextureView {
+        let views = &self.textures[handle.0].views;
+        &views[self.parity % views.len()]
+    }
+
+    fn allocate_texture(&mut self, device: &Device, index: usize) {
+        let (width, height) = match self.textures[index].desc.size {
+            TextureSize::Screen => self.dimensions,
+            TextureSize::Fixed(width, height) => (width, height),
+        };
+        let resource = &mut self.textures[index];
+        let versions = if resource.desc.history { 2 } else { 1 };
+
+        resource.textures = (0..versions)
+            .map(|_| device.create_texture(&wgpu::TextureDescriptor {
+                label: Some(resource.desc.label),
+                size: wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: resource.desc.format,
+                usage: resource.usage,
+                view_formats: &[],
+            }))
+            .collect();
+        resource.views = resource.textures
+            .iter()
+            .map(|texture| texture.create_view(&wgpu::TextureViewDescriptor::default()))
+            .collect();
+    }
+
+    fn rebuild_bind_groups(&mut self, device: &Device, index: usize) {
+        let node = &self.passes[index];
+        let bind_groups = (0..2)
+            .map(|parity| {
+                let version = |h: TextureHandle, offset: usize| {
+                    let views = &self.textures[h.0].views;
+                    &views[(parity + offset) % views.len()]
+                };
+                let entries: Vec<_> = node.bindings
+                    .iter()
+                    .enumerate()
+                    .map(|(slot, binding)| wgpu::BindGroupEntry {
+                        binding: slot as u32,
+                        resource: match *binding {
+                            Binding::Uniform(h) | Binding::Storage(h) => self.buffers[h.0].as_entire_binding(),
+                            Binding::Sampled(h) | Binding::Write(h) => {
+                                wgpu::BindingResource::TextureView(version(h, 0))
+                            }
+                            Binding::History(h) => wgpu::BindingResource::TextureView(version(h, 1)),
+                            Binding::Sampler(h) => wgpu::BindingResource::Sampler(&self.samplers[h.0]),
+                        },
+                    })
+                    .collect();
+
+                device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some(node.label),
+                    layout: &node.layout,
+                    entries: &entries,
+                })
+            })
+            .collect();
+
+        self.passes[index].bind_groups = bind_groups;
+    }
+}
@@ -1,5 +1,186 @@
+pub mod brickmap;
+pub mod graph;
+pub mod palette;
 pub mod pipeline;
+pub mod profiler;
+pub mod raster;
 pub mod resources;
+pub mod streaming;
 
-pub use pipeline::{RayMarchingPipeline, Camera, SceneConfig};
+pub use brickmap::GpuBrickmap;
+pub use graph::{RenderGraph, GraphError, RasterHook};
+pub use palette::MaterialPalette;
+pub use pipeline::{RayMarchingPipeline, Camera, SceneConfig, COLOR_FORMAT, DEPTH_FORMAT};
+pub use profiler::{GpuProfiler, PassTiming};
+pub use raster::RasterPipeline;
 pub use resources::{GPUResources, Mesh, Texture, Buffer};
+pub use streaming::ChunkPool;
+
+use std::collections::HashSet;
+use crate::window::EngineWindow;
+use crate::world::{Brickmap, ChunkPos, SparseVoxelDag, World};
+
+/// which pipeline draws the world, picked once at startup
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RendererBackend {
+    /// compute ray marching into a storage texture
+    #[default]
+    RayMarching,
+    /// greedy-meshed chunks through a vertex/fragment pipeline
+    Rasterization,
+}
+
+enum Backend {
+    RayMarching(RayMarchingPipeline),
+    Rasterization(RasterPipeline),
+}
+
+pub struct Renderer {
+    gpu: GPUResources,
+    surface_config: wgpu::SurfaceConfiguration,
+    backend: Backend,
+    pub camera: Camera,
+    pub scene: SceneConfig,
+}
+
+impl Renderer {
+    pub async fn new(window: &EngineWindow, backend: RendererBackend) -> Self {
+        let gpu = GPUResources::new(window).await;
+        let (width, height) = window.size;
+
+        // the ray marcher copies its rgba8 output straight into the surface
+        let caps = gpu.surface.get_capabilities(&gpu.adapter);
+        let format = if caps.formats.contains(&COLOR_FORMAT) { COLOR_FORMAT } else { caps.formats[0] };
+        let surface_config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_DST,
+            format,
+            width,
+            height,
+            present_mode: wgpu::PresentMode::AutoVsync,
+            alpha_mode: caps.alpha_modes[0],
+            view_formats: vec![],
+            desired_maximum_frame_latency: 2,
+        };
+        gpu.surface.configure(&gpu.device, &surface_config);
+
+        let backend = match backend {
+            RendererBackend::RayMarching => {
+                Backend::RayMarching(RayMarchingPipeline::new(&gpu.device, width, height))
+            }
+            RendererBackend::Rasterization => {
+                Backend::Rasterization(RasterPipeline::new(&gpu.device, format, width, height))
+            }
+        };
+
+        Self {
+            gpu,
+            surface_config,
+            backend,
+            camera: Camera::new(width, height),
+            scene: SceneConfig::default(),
+        }
+    }
+
+    pub fn backend(&self) -> RendererBackend {
+        match self.backend {
+            Backend::RayMarching(_) => RendererBackend::RayMarching,
+            Backend::Rasterization(_) => RendererBackend::Rasterization,
+        }
+    }
+
+    pub fn resize(&mut self, width: u32, height: u32) {
+        if width == 0 || height == 0 {
+            return;
+        }
+
+        self.surface_config.width = width;
+        self.surface_config.height = height;
+        self.gpu.surface.configure(&self.gpu.device, &self.surface_config);
+        self.camera.aspect = width as f32 / height as f32;
+
+        match &mut self.backend {
+            Backend::RayMarching(pipeline) => pipeline.resize(&self.gpu.device, width, height),
+            Backend::Rasterization(pipeline) => pipeline.resize(&self.gpu.device, width, height),
+        }
+    }
+
+    pub fn set_palette(&self, palette: &MaterialPalette) {
+        match &self.backend {
+            Backend::RayMarching(pipeline) => pipeline.set_palette(&self.gpu.queue, palette),
+            Backend::Rasterization(pipeline) => pipeline.set_palette(&self.gpu.queue, palette),
+        }
+    }
+
+    /// trace a baked dag with the world. returns false on backends that can't draw one
+    pub fn set_static_scene(&mut self, dag: &SparseVoxelDag) -> bool {
+        match &mut self.backend {
+            Backend::RayMarching(pipeline) => {
+                pipeline.set_static_scene(&self.gpu.device, dag);
+                true
+            }
+            Backend::Rasterization(_) => false,
+        }
+    }
+
+    /// upload a brickmap's pending edits for tracing with the world. returns
+    /// false on backends that can't draw one
+    pub fn sync_brickmap(&mut self, map: &mut Brickmap) -> bool {
+        match &mut self.backend {
+            Backend::RayMarching(pipeline) => {
+                pipeline.sync_brickmap(&self.gpu.device, &self.gpu.queue, map);
+                true
+            }
+            Backend::Rasterization(_) => false,
+        }
+    }
+
+    pub fn render(&mut self, world: &World) {
+        let frame = match self.gpu.surface.get_current_texture() {
+            Ok(frame) => frame,
+            Err(err) => {
+                log::warn!("skipping frame: {err}");
+                return;
+            }
+        };
+
+        let dirty = world.take_dirty();
+        let GPUResources { device, queue, .. } = &self.gpu;
+        match &mut self.backend {
+            Backend::RayMarching(pipeline) => {
+                pipeline.sync_world(device, queue, world, &dirty, &self.camera, &self.scene);
+                pipeline.update_camera(queue, &self.camera, &self.scene);
+                pipeline.render(device, queue, &frame.texture);
+            }
+            Backend::Rasterization(pipeline) => {
+                let loaded: HashSet<_> = world.loaded_chunks().into_iter().collect();
+
+                // edits change culling and ao in the chunks around them too
+                for pos in &dirty {
+                    for dz in -1..=1 {
+                        for dy in -1..=1 {
+                            for dx in -1..=1 {
+                                pipeline.remove_chunk(ChunkPos { x: pos.x + dx, y: pos.y + dy, z: pos.z + dz });
+                            }
+                        }
+                    }
+                }
+
+                let unloaded: Vec<_> = pipeline.chunk_positions().filter(|pos| !loaded.contains(pos)).collect();
+                for pos in unloaded {
+                    pipeline.remove_chunk(pos);
+                }
+                for &pos in &loaded {
+                    if !pipeline.has_chunk(pos) {
+                        if let Some(mesh) = world.mesh_chunk(pos) {
+                            pipeline.upload_chunk(device, pos, &mesh);
+                        }
+                    }
+                }
+
+                pipeline.render(device, queue, &self.camera, &frame.texture);
+            }
+        }
+
+        frame.present();
+    }
+}
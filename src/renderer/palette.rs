@@ -0,0 +1,44 @@
+use bytemuck::{Pod, Zeroable};
+use crate::world::block;
+
+/// colour for every block id, shared by the ray marching and raster backends
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+pub struct MaterialPalette {
+    pub colors: [[f32; 4]; 256],
+}
+
+impl MaterialPalette {
+    pub fn get(&self, block: u8) -> [f32; 4] {
+        self.colors[block as usize]
+    }
+
+    pub fn set(&mut self, block: u8, color: [f32; 4]) {
+        self.colors[block as usize] = color;
+    }
+}
+
+impl Default for MaterialPalette {
+    fn default() -> Self {
+        // unknown blocks show up magenta, air stays invisible
+        let mut palette = Self {
+            colors: [[1.0, 0.0, 1.0, 1.0]; 256],
+        };
+        palette.set(block::AIR, [0.0; 4]);
+        palette.set(block::STONE, [0.5, 0.5, 0.5, 1.0]);
+        palette.set(block::DIRT, [0.45, 0.32, 0.2, 1.0]);
+        palette.set(block::GRASS, [0.3, 0.6, 0.2, 1.0]);
+        palette.set(block::SAND, [0.86, 0.8, 0.55, 1.0]);
+        palette.set(block::SNOW, [0.95, 0.97, 1.0, 1.0]);
+        palette.set(block::WATER, [0.15, 0.35, 0.75, 0.7]);
+        palette.set(block::LOG, [0.4, 0.28, 0.15, 1.0]);
+        palette.set(block::LEAVES, [0.2, 0.5, 0.15, 1.0]);
+        palette.set(block::GRAVEL, [0.55, 0.52, 0.5, 1.0]);
+        palette.set(block::TALL_GRASS, [0.35, 0.7, 0.25, 1.0]);
+        palette.set(block::CACTUS, [0.25, 0.55, 0.2, 1.0]);
+        palette.set(block::FLOWER, [0.9, 0.3, 0.35, 1.0]);
+        palette.set(block::COAL_ORE, [0.25, 0.25, 0.27, 1.0]);
+        palette.set(block::IRON_ORE, [0.7, 0.55, 0.45, 1.0]);
+        palette
+    }
+}
@@ -1,6 +1,27 @@
-use std::borrow::Cow;
-use wgpu::{Device, Queue, Surface};
+use wgpu::{Device, Queue};
+use wgpu::util::DeviceExt;
 use bytemuck::{Pod, Zeroable};
+use glam::Mat4;
+use crate::utils::math::{halton, Frustum, Matrix};
+use super::graph::{
+    Binding, BufferHandle, ComputePassDesc, DepthAttachment, PassHandle, RasterHook,
+    RasterPassDesc, RenderGraph, TextureDesc, TextureHandle, TextureSize,
+};
+use super::brickmap::GpuBrickmap;
+use super::palette::MaterialPalette;
+use super::profiler::{GpuProfiler, PassTiming};
+use super::streaming::ChunkPool;
+use crate::world::{Brickmap, ChunkPos, SparseVoxelDag, World, OCTREE_LEAF};
+
+/// length of the halton jitter cycle, in frames
+const JITTER_PHASES: u32 = 16;
+/// chunk slots the pool starts with, it grows on demand
+const INITIAL_POOL_SLOTS: u32 = 256;
+
+/// colour format raster hooks draw into
+pub const COLOR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+/// depth buffer raster hooks test against, in `Matrix::perspective` convention (0 near, 1 far)
+pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
 
 #[repr(C)]
 #[derive(Debug, Clone, Copy, Pod, Zeroable)]
@@ -10,15 +31,37 @@ pub struct RayMarchingUniforms {
     pub max_steps: u32,
     pub max_distance: f32,
     pub min_distance: f32,
-    padding: [u32; 3],
+    pub frame_index: u32,
+    pub jitter: [f32; 2],
+    pub inv_view_proj: [[f32; 4]; 4],
+    pub prev_view_proj: [[f32; 4]; 4],
+    pub view_proj: [[f32; 4]; 4],
+    pub taa_enabled: u32,
+    pub history_blend: f32,
+    /// angle one pixel subtends, sizes the ray cone for lod selection
+    pub pixel_angle: f32,
+    pub lod_bias: f32,
 }
 
 pub struct RayMarchingPipeline {
-    pipeline: wgpu::ComputePipeline,
-    uniform_buffer: wgpu::Buffer,
-    bind_group: wgpu::BindGroup,
-    output_texture: wgpu::Texture,
-    dimensions: (u32, u32),
+    graph: RenderGraph,
+    uniforms: BufferHandle,
+    palette: BufferHandle,
+    pool: ChunkPool,
+    pool_blocks: BufferHandle,
+    pool_initialised: bool,
+    static_scene: BufferHandle,
+    brickmap: GpuBrickmap,
+    brick_grid: BufferHandle,
+    bricks: BufferHandle,
+    color: TextureHandle,
+    history: TextureHandle,
+    taa_pass: PassHandle,
+    overlay_pass: PassHandle,
+    frame_index: u32,
+    prev_view_proj: Mat4,
+    taa_enabled: bool,
+    profiler: Option<GpuProfiler>,
 }
 
 impl RayMarchingPipeline {
@@ -27,18 +70,21 @@ impl RayMarchingPipeline {
         width: u32,
         height: u32,
     ) -> Self {
-        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("Ray Marching Compute Shader"),
-            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("../../assets/shaders/ray_march.wgsl"))),
-        });
-
         let uniforms = RayMarchingUniforms {
             view_position: [0.0, 0.0, -5.0, 1.0],
             screen_size: [width as f32, height as f32],
             max_steps: 100,
             max_distance: 100.0,
             min_distance: 0.001,
-            padding: [0; 3],
+            frame_index: 0,
+            jitter: [0.0; 2],
+            inv_view_proj: Mat4::IDENTITY.to_cols_array_2d(),
+            prev_view_proj: Mat4::IDENTITY.to_cols_array_2d(),
+            view_proj: Mat4::IDENTITY.to_cols_array_2d(),
+            taa_enabled: 0,
+            history_blend: 0.1,
+            pixel_angle: 0.0,
+            lod_bias: 1.0,
         };
 
         let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
@@ -47,134 +93,301 @@ impl RayMarchingPipeline {
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         });
 
-        let output_texture = device.create_texture(&wgpu::TextureDescriptor {
-            label: Some("Ray Marching Output Texture"),
-            size: wgpu::Extent3d {
-                width,
-                height,
-                depth_or_array_layers: 1,
-            },
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
+        let palette_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Material Palette"),
+            contents: bytemuck::cast_slice(&[MaterialPalette::default()]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let pool = ChunkPool::new(device, INITIAL_POOL_SLOTS, SceneConfig::default().chunk_memory_budget);
+
+        let mut graph = RenderGraph::new(width, height);
+        let uniforms = graph.import_buffer(uniform_buffer);
+        let palette = graph.import_buffer(palette_buffer);
+        let indirection = graph.import_buffer(pool.indirection().clone());
+        let pool_blocks = graph.import_buffer(pool.blocks().clone());
+        let fallbacks = graph.import_buffer(pool.fallbacks().clone());
+        let static_scene = graph.import_buffer(Self::create_static_scene(device, &Self::EMPTY_STATIC_SCENE));
+        let brickmap = GpuBrickmap::new(device);
+        let brick_grid = graph.import_buffer(brickmap.grid().clone());
+        let bricks = graph.import_buffer(brickmap.bricks().clone());
+
+        let color = graph.create_texture(TextureDesc {
+            label: "Ray Marching Output Texture",
+            format: COLOR_FORMAT,
+            size: TextureSize::Screen,
+            usage: wgpu::TextureUsages::COPY_SRC,
+            history: false,
+        });
+        let distance = graph.create_texture(TextureDesc {
+            label: "Ray Marching Distance Texture",
+            format: wgpu::TextureFormat::R32Float,
+            size: TextureSize::Screen,
+            usage: wgpu::TextureUsages::empty(),
+            history: false,
+        });
+        let ndc_depth = graph.create_texture(TextureDesc {
+            label: "Ray Marching Depth Texture",
+            format: wgpu::TextureFormat::R32Float,
+            size: TextureSize::Screen,
+            usage: wgpu::TextureUsages::empty(),
+            history: false,
+        });
+        let depth = graph.create_texture(TextureDesc {
+            label: "Scene Depth Buffer",
+            format: DEPTH_FORMAT,
+            size: TextureSize::Screen,
+            usage: wgpu::TextureUsages::empty(),
+            history: false,
+        });
+        let history = graph.create_texture(TextureDesc {
+            label: "Temporal AA History Texture",
             format: wgpu::TextureFormat::Rgba8Unorm,
-            usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::COPY_SRC,
-            view_formats: &[],
+            size: TextureSize::Screen,
+            usage: wgpu::TextureUsages::COPY_SRC,
+            history: true,
+        });
+        let history_sampler = graph.create_sampler(device, &wgpu::SamplerDescriptor {
+            label: Some("Temporal AA History Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
         });
 
-        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            label: Some("Ray Marching Bind Group Layout"),
-            entries: &[
-                wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::COMPUTE,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-                wgpu::BindGroupLayoutEntry {
-                    binding: 1,
-                    visibility: wgpu::ShaderStages::COMPUTE,
-                    ty: wgpu::BindingType::StorageTexture {
-                        access: wgpu::StorageTextureAccess::WriteOnly,
-                        format: wgpu::TextureFormat::Rgba8Unorm,
-                        view_dimension: wgpu::TextureViewDimension::D2,
-                    },
-                    count: None,
-                },
+        graph.add_compute_pass(device, ComputePassDesc {
+            label: "ray march",
+            shader: include_str!("../../assets/shaders/ray_march.wgsl"),
+            bindings: vec![
+                Binding::Uniform(uniforms),
+                Binding::Write(color),
+                Binding::Write(distance),
+                Binding::Write(ndc_depth),
+                Binding::Uniform(palette),
+                Binding::Storage(indirection),
+                Binding::Storage(pool_blocks),
+                Binding::Storage(fallbacks),
+                Binding::Storage(static_scene),
+                Binding::Storage(brick_grid),
+                Binding::Storage(bricks),
             ],
+            workgroup_size: (8, 8),
         });
 
-        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            label: Some("Ray Marching Pipeline Layout"),
-            bind_group_layouts: &[&bind_group_layout],
-            push_constant_ranges: &[],
+        // storage textures can't hold depth formats, so copy it across with frag_depth
+        graph.add_raster_pass(device, RasterPassDesc {
+            label: "depth resolve",
+            shader: Some(include_str!("../../assets/shaders/depth_resolve.wgsl")),
+            bindings: vec![Binding::Sampled(ndc_depth)],
+            color: None,
+            depth: Some(DepthAttachment {
+                texture: depth,
+                clear: true,
+                write: true,
+                compare: wgpu::CompareFunction::Always,
+            }),
         });
 
-        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
-            label: Some("Ray Marching Pipeline"),
-            layout: Some(&pipeline_layout),
-            module: &shader,
-            entry_point: Some("main"),
-            compilation_options: None,
-            cache: None,
+        let overlay_pass = graph.add_raster_pass(device, RasterPassDesc {
+            label: "raster overlay",
+            shader: None,
+            bindings: Vec::new(),
+            color: Some(color),
+            depth: Some(DepthAttachment {
+                texture: depth,
+                clear: false,
+                write: false,
+                compare: wgpu::CompareFunction::Less,
+            }),
         });
 
-        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("Ray Marching Bind Group"),
-            layout: &bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: uniform_buffer.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::TextureView(
-                        &output_texture.create_view(&wgpu::TextureViewDescriptor::default())
-                    ),
-                },
+        let taa_pass = graph.add_compute_pass(device, ComputePassDesc {
+            label: "post",
+            shader: include_str!("../../assets/shaders/taa.wgsl"),
+            bindings: vec![
+                Binding::Uniform(uniforms),
+                Binding::Sampled(color),
+                Binding::Sampled(distance),
+                Binding::History(history),
+                Binding::Sampler(history_sampler),
+                Binding::Write(history),
             ],
+            workgroup_size: (8, 8),
         });
 
+        graph.build(device).expect("ray marching render graph is malformed");
+
         Self {
-            pipeline,
-            uniform_buffer,
-            bind_group,
-            output_texture,
-            dimensions: (width, height),
+            graph,
+            uniforms,
+            palette,
+            pool,
+            pool_blocks,
+            static_scene,
+            brickmap,
+            brick_grid,
+            bricks,
+            pool_initialised: false,
+            color,
+            history,
+            taa_pass,
+            overlay_pass,
+            frame_index: 0,
+            prev_view_proj: Mat4::IDENTITY,
+            taa_enabled: false,
+            profiler: None,
+        }
+    }
+
+    /// start timing each pass. returns false if the device has no timestamp support
+    pub fn enable_profiling(&mut self, device: &Device, queue: &Queue) -> bool {
+        self.profiler = GpuProfiler::new(device, queue);
+        self.profiler.is_some()
+    }
+
+    /// latest per-pass gpu timings, empty when profiling is off or still warming up
+    pub fn pass_timings(&self) -> &[PassTiming] {
+        self.profiler.as_ref().map_or(&[], |profiler| profiler.timings())
+    }
+
+    /// draw rasterised geometry over the voxels, after the ray march and before taa.
+    /// hooks render into `COLOR_FORMAT` and should depth test against `DEPTH_FORMAT`
+    pub fn add_raster_hook(&mut self, hook: Box<dyn RasterHook>) {
+        self.graph.add_hook(self.overlay_pass, hook);
+    }
+
+    pub fn set_palette(&self, queue: &Queue, palette: &MaterialPalette) {
+        queue.write_buffer(self.graph.buffer(self.palette), 0, bytemuck::cast_slice(&[*palette]));
+    }
+
+    /// stream changed chunks to the gpu, keep the pool under budget, and keep
+    /// the indirection window around the camera
+    pub fn sync_world(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        world: &World,
+        dirty: &[ChunkPos],
+        camera: &Camera,
+        config: &SceneConfig,
+    ) {
+        let frustum = Frustum::from_matrix(&Matrix(Mat4::from_cols_array_2d(&camera.build_view_projection_matrix())));
+        self.pool.set_budget(config.chunk_memory_budget);
+
+        let grown = if self.pool_initialised {
+            self.pool.update(device, queue, world, dirty, &frustum)
+        } else {
+            self.pool_initialised = true;
+            self.pool.upload_all(device, queue, world, &frustum)
+        };
+
+        if grown {
+            self.graph.replace_buffer(device, self.pool_blocks, self.pool.blocks().clone());
+        }
+
+        let [x, y, z] = camera.position.map(|c| c.floor() as i32);
+        self.pool.recenter(queue, ChunkPos::from_world(x, y, z));
+    }
+
+    /// trace a baked dag alongside the streamed chunks, whichever is hit first wins
+    pub fn set_static_scene(&mut self, device: &Device, dag: &SparseVoxelDag) {
+        let buffer = Self::create_static_scene(device, &dag.encode_gpu());
+        self.graph.replace_buffer(device, self.static_scene, buffer);
+    }
+
+    pub fn clear_static_scene(&mut self, device: &Device) {
+        let buffer = Self::create_static_scene(device, &Self::EMPTY_STATIC_SCENE);
+        self.graph.replace_buffer(device, self.static_scene, buffer);
+    }
+
+    /// upload a brickmap's edits; it is traced alongside the chunks, nearest hit wins
+    pub fn sync_brickmap(&mut self, device: &Device, queue: &Queue, map: &mut Brickmap) {
+        let (grid, bricks) = self.brickmap.sync(device, queue, map);
+        if grid {
+            self.graph.replace_buffer(device, self.brick_grid, self.brickmap.grid().clone());
         }
+        if bricks {
+            self.graph.replace_buffer(device, self.bricks, self.brickmap.bricks().clone());
+        }
+    }
+
+    /// zero size tells the shader there is no static scene
+    const EMPTY_STATIC_SCENE: [u32; 5] = [0, 0, 0, 0, OCTREE_LEAF];
+
+    fn create_static_scene(device: &Device, words: &[u32]) -> wgpu::Buffer {
+        device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Static Scene Dag"),
+            contents: bytemuck::cast_slice(words),
+            usage: wgpu::BufferUsages::STORAGE,
+        })
+    }
+
+    /// gpu bytes held by resident chunks
+    pub fn chunk_memory_used(&self) -> u64 {
+        self.pool.memory_used()
     }
 
     pub fn update_uniforms(&self, queue: &Queue, uniforms: RayMarchingUniforms) {
         queue.write_buffer(
-            &self.uniform_buffer,
+            self.graph.buffer(self.uniforms),
             0,
             bytemuck::cast_slice(&[uniforms])
         );
     }
 
-    pub fn resize(&mut self, device: &Device, width: u32, height: u32) {
-        self.dimensions = (width, height);
-        
-        self.output_texture = device.create_texture(&wgpu::TextureDescriptor {
-            label: Some("Ray Marching Output Texture"),
-            size: wgpu::Extent3d {
-                width,
-                height,
-                depth_or_array_layers: 1,
-            },
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba8Unorm,
-            usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::COPY_SRC,
-            view_formats: &[],
-        });
+    /// build this frame's uniforms from the camera, advancing the jitter sequence
+    pub fn update_camera(&mut self, queue: &Queue, camera: &Camera, config: &SceneConfig) {
+        let view_proj = Mat4::from_cols_array_2d(&camera.build_view_projection_matrix());
+        let dimensions = self.graph.dimensions();
 
-        self.bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("Ray Marching Bind Group"),
-            layout: &self.pipeline.get_bind_group_layout(0),
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: self.uniform_buffer.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::TextureView(
-                        &self.output_texture.create_view(&wgpu::TextureViewDescriptor::default())
-                    ),
-                },
-            ],
+        // toggling taa on starts from a clean history
+        if config.taa && !self.taa_enabled {
+            self.frame_index = 0;
+        }
+
+        // offsets are in pixels, centred on zero. without taa every ray goes through the pixel centre
+        let phase = self.frame_index % JITTER_PHASES + 1;
+        let jitter = if config.taa {
+            [halton(phase, 2) - 0.5, halton(phase, 3) - 0.5]
+        } else {
+            [0.0; 2]
+        };
+
+        // the first frame has no history to reproject into
+        let prev_view_proj = if self.frame_index == 0 { view_proj } else { self.prev_view_proj };
+
+        self.update_uniforms(queue, RayMarchingUniforms {
+            view_position: [camera.position[0], camera.position[1], camera.position[2], 1.0],
+            screen_size: [dimensions.0 as f32, dimensions.1 as f32],
+            max_steps: config.max_steps,
+            max_distance: config.max_distance,
+            min_distance: config.min_distance,
+            frame_index: self.frame_index,
+            jitter,
+            inv_view_proj: view_proj.inverse().to_cols_array_2d(),
+            prev_view_proj: prev_view_proj.to_cols_array_2d(),
+            view_proj: view_proj.to_cols_array_2d(),
+            taa_enabled: config.taa as u32,
+            history_blend: config.taa_history_blend,
+            pixel_angle: 2.0 * (camera.fov.to_radians() * 0.5).tan() / dimensions.1 as f32,
+            lod_bias: config.lod_bias,
         });
+
+        self.taa_enabled = config.taa;
+        self.prev_view_proj = view_proj;
+        self.frame_index = self.frame_index.wrapping_add(1);
+    }
+
+    pub fn resize(&mut self, device: &Device, width: u32, height: u32) {
+        self.graph.resize(device, width, height);
+
+        // history no longer lines up with the new resolution
+        self.frame_index = 0;
     }
 
     pub fn render(
-        &self,
+        &mut self,
         device: &Device,
         queue: &Queue,
         output_texture: &wgpu::Texture,
@@ -183,23 +396,23 @@ impl RayMarchingPipeline {
             label: Some("Ray Marching Encoder"),
         });
 
-        {
-            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
-                label: Some("Ray Marching Compute Pass"),
-                timestamp_writes: None,
-            });
-            compute_pass.set_pipeline(&self.pipeline);
-            compute_pass.set_bind_group(0, &self.bind_group, &[]);
-            compute_pass.dispatch_workgroups(
-                (self.dimensions.0 + 7) / 8,
-                (self.dimensions.1 + 7) / 8,
-                1
-            );
+        if let Some(profiler) = &mut self.profiler {
+            profiler.begin_frame();
         }
 
-        encoder.copy_texture_to_texture(
+        self.graph.set_enabled(self.taa_pass, self.taa_enabled);
+        self.graph.execute(&mut encoder, self.profiler.as_mut());
+
+        let resolved = self.graph.texture(if self.taa_enabled { self.history } else { self.color });
+        let (width, height) = self.graph.dimensions();
+        let extent = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+        let present = |encoder: &mut wgpu::CommandEncoder| encoder.copy_texture_to_texture(
             wgpu::ImageCopyTexture {
-                texture: &self.output_texture,
+                texture: resolved,
                 mip_level: 0,
                 origin: wgpu::Origin3d::ZERO,
                 aspect: wgpu::TextureAspect::All,
@@ -210,14 +423,23 @@ impl RayMarchingPipeline {
                 origin: wgpu::Origin3d::ZERO,
                 aspect: wgpu::TextureAspect::All,
             },
-            wgpu::Extent3d {
-                width: self.dimensions.0,
-                height: self.dimensions.1,
-                depth_or_array_layers: 1,
-            }
+            extent,
         );
 
+        match &mut self.profiler {
+            Some(profiler) => {
+                profiler.scope("present", &mut encoder, present);
+                profiler.resolve(&mut encoder);
+            }
+            None => present(&mut encoder),
+        }
+
         queue.submit(Some(encoder.finish()));
+
+        if let Some(profiler) = &mut self.profiler {
+            profiler.end_frame(device);
+        }
+        self.graph.end_frame();
     }
 }
 
@@ -268,14 +490,27 @@ pub struct SceneConfig {
     pub max_steps: u32,
     pub max_distance: f32,
     pub min_distance: f32,
+    /// jitter rays per frame and accumulate them into a reprojected history
+    pub taa: bool,
+    /// weight of the current frame when blending into the history
+    pub taa_history_blend: f32,
+    /// gpu bytes resident chunks may use before the least recently visible are evicted
+    pub chunk_memory_budget: u64,
+    /// voxels a pixel may cover before marching drops to the next coarser mip;
+    /// higher keeps full detail further out
+    pub lod_bias: f32,
 }
 
 impl Default for SceneConfig {
     fn default() -> Self {
         Self {
-            max_steps: 100,
-            max_distance: 100.0,
+            max_steps: 512,
+            max_distance: 256.0,
             min_distance: 0.001,
+            taa: true,
+            taa_history_blend: 0.1,
+            chunk_memory_budget: 256 * 1024 * 1024,
+            lod_bias: 1.0,
         }
     }
 }
@@ -0,0 +1,225 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use wgpu::{Device, Queue};
+
+/// most scopes recorded in a single frame
+const MAX_SCOPES: u32 = 8;
+/// frames that may be waiting on a readback at once
+const READBACK_FRAMES: usize = 3;
+
+/// gpu time spent in one render pass
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PassTiming {
+    pub label: &'static str,
+    pub duration_ms: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ReadbackState {
+    Free,
+    /// copy recorded, waiting for the frame to be submitted
+    Resolved,
+    /// `map_async` requested, waiting on the gpu
+    Mapping,
+}
+
+struct Readback {
+    buffer: wgpu::Buffer,
+    labels: Vec<&'static str>,
+    state: ReadbackState,
+    ready: Arc<AtomicBool>,
+}
+
+impl Readback {
+    fn size(&self) -> u64 {
+        self.labels.len() as u64 * 2 * std::mem::size_of::<u64>() as u64
+    }
+}
+
+/// timestamp queries around render passes, read back a few frames late so the cpu never stalls
+pub struct GpuProfiler {
+    query_set: wgpu::QuerySet,
+    resolve_buffer: wgpu::Buffer,
+    readbacks: Vec<Readback>,
+    scopes: Vec<&'static str>,
+    encoder_timestamps: bool,
+    period: f32,
+    latest: Vec<PassTiming>,
+}
+
+impl GpuProfiler {
+    /// features worth requesting on the device when they are available
+    pub fn features(adapter: &wgpu::Adapter) -> wgpu::Features {
+        adapter.features()
+            & (wgpu::Features::TIMESTAMP_QUERY | wgpu::Features::TIMESTAMP_QUERY_INSIDE_ENCODERS)
+    }
+
+    /// `None` when the device was created without timestamp support
+    pub fn new(device: &Device, queue: &Queue) -> Option<Self> {
+        if !device.features().contains(wgpu::Features::TIMESTAMP_QUERY) {
+            log::info!("timestamp queries unsupported, gpu profiling disabled");
+            return None;
+        }
+
+        let count = MAX_SCOPES * 2;
+        let size = count as u64 * std::mem::size_of::<u64>() as u64;
+
+        let query_set = device.create_query_set(&wgpu::QuerySetDescriptor {
+            label: Some("Profiler Query Set"),
+            ty: wgpu::QueryType::Timestamp,
+            count,
+        });
+
+        let resolve_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Profiler Resolve Buffer"),
+            size,
+            usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+
+        let readbacks = (0..READBACK_FRAMES)
+            .map(|_| Readback {
+                buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("Profiler Readback Buffer"),
+                    size,
+                    usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                }),
+                labels: Vec::new(),
+                state: ReadbackState::Free,
+                ready: Arc::new(AtomicBool::new(false)),
+            })
+            .collect();
+
+        Some(Self {
+            query_set,
+            resolve_buffer,
+            readbacks,
+            scopes: Vec::new(),
+            encoder_timestamps: device.features().contains(wgpu::Features::TIMESTAMP_QUERY_INSIDE_ENCODERS),
+            period: queue.get_timestamp_period(),
+            latest: Vec::new(),
+        })
+    }
+
+    /// start recording a new frame's scopes
+    pub fn begin_frame(&mut self) {
+        self.scopes.clear();
+    }
+
+    /// timestamp writes for a compute pass, `None` once the frame is out of queries
+    pub fn compute_pass(&mut self, label: &'static str) -> Option<wgpu::ComputePassTimestampWrites<'_>> {
+        let index = self.push_scope(label)?;
+        Some(wgpu::ComputePassTimestampWrites {
+            query_set: &self.query_set,
+            beginning_of_pass_write_index: Some(index),
+            end_of_pass_write_index: Some(index + 1),
+        })
+    }
+
+    /// timestamp writes for a render pass, `None` once the frame is out of queries
+    pub fn render_pass(&mut self, label: &'static str) -> Option<wgpu::RenderPassTimestampWrites<'_>> {
+        let index = self.push_scope(label)?;
+        Some(wgpu::RenderPassTimestampWrites {
+            query_set: &self.query_set,
+            beginning_of_pass_write_index: Some(index),
+            end_of_pass_write_index: Some(index + 1),
+        })
+    }
+
+    /// time encoder commands outside of a pass, e.g. copies. skipped if the device can't do it
+    pub fn scope<R>(
+        &mut self,
+        label: &'static str,
+        encoder: &mut wgpu::CommandEncoder,
+        f: impl FnOnce(&mut wgpu::CommandEncoder) -> R,
+    ) -> R {
+        let index = if self.encoder_timestamps { self.push_scope(label) } else { None };
+        if let Some(index) = index {
+            encoder.write_timestamp(&self.query_set, index);
+        }
+        let result = f(encoder);
+        if let Some(index) = index {
+            encoder.write_timestamp(&self.query_set, index + 1);
+        }
+        result
+    }
+
+    /// copy this frame's queries into a free readback buffer
+    pub fn resolve(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        if self.scopes.is_empty() {
+            return;
+        }
+
+        // every readback still waiting on the gpu, drop this frame's numbers
+        let Some(readback) = self.readbacks.iter_mut().find(|r| r.state == ReadbackState::Free) else {
+            return;
+        };
+
+        let count = self.scopes.len() as u32 * 2;
+        let size = count as u64 * std::mem::size_of::<u64>() as u64;
+        encoder.resolve_query_set(&self.query_set, 0..count, &self.resolve_buffer, 0);
+        encoder.copy_buffer_to_buffer(&self.resolve_buffer, 0, &readback.buffer, 0, size);
+
+        readback.labels = std::mem::take(&mut self.scopes);
+        readback.state = ReadbackState::Resolved;
+    }
+
+    /// map readbacks submitted this frame and collect any that have finished.
+    /// call after the frame's command buffer has been submitted
+    pub fn end_frame(&mut self, device: &Device) {
+        for readback in &mut self.readbacks {
+            if readback.state == ReadbackState::Resolved {
+                let ready = readback.ready.clone();
+                readback.buffer.slice(..readback.size()).map_async(wgpu::MapMode::Read, move |result| {
+                    if result.is_ok() {
+                        ready.store(true, Ordering::Release);
+                    }
+                });
+                readback.state = ReadbackState::Mapping;
+            }
+        }
+
+        device.poll(wgpu::Maintain::Poll);
+
+        for readback in &mut self.readbacks {
+            if !readback.ready.swap(false, Ordering::AcqRel) {
+                continue;
+            }
+
+            {
+                let data = readback.buffer.slice(..readback.size()).get_mapped_range();
+                let ticks: &[u64] = bytemuck::cast_slice(&data);
+                self.latest = readback.labels
+                    .iter()
+                    .zip(ticks.chunks_exact(2))
+                    .map(|(&label, pair)| PassTiming {
+                        label,
+                        duration_ms: pair[1].wrapping_sub(pair[0]) as f32 * self.period / 1_000_000.0,
+                    })
+                    .collect();
+            }
+            readback.buffer.unmap();
+            readback.labels.clear();
+            readback.state = ReadbackState::Free;
+
+            for timing in &self.latest {
+                tracing::trace!(pass = timing.label, ms = timing.duration_ms, "gpu pass timing");
+            }
+        }
+    }
+
+    /// most recent complete set of pass timings
+    pub fn timings(&self) -> &[PassTiming] {
+        &self.latest
+    }
+
+    fn push_scope(&mut self, label: &'static str) -> Option<u32> {
+        if self.scopes.len() as u32 >= MAX_SCOPES {
+            return None;
+        }
+        let index = self.scopes.len() as u32 * 2;
+        self.scopes.push(label);
+        Some(index)
+    }
+}
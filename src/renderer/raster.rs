@@ -0,0 +1,294 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use wgpu::{Device, Queue};
+use wgpu::util::DeviceExt;
+use bytemuck::{Pod, Zeroable};
+use crate::utils::math::{Frustum, Matrix, Vec3f};
+use crate::world::{Chunk, ChunkPos, MeshData};
+use super::palette::MaterialPalette;
+use super::pipeline::{Camera, DEPTH_FORMAT};
+use super::resources::Mesh;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct RasterUniforms {
+    view_proj: [[f32; 4]; 4],
+    light_dir: [f32; 4],
+}
+
+/// per-draw chunk origin, fed as an instance attribute
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct ChunkInstance {
+    origin: [f32; 3],
+}
+
+struct ChunkMesh {
+    mesh: Mesh,
+    instance: wgpu::Buffer,
+}
+
+/// draws meshed chunks with a vertex/fragment pipeline instead of ray marching
+pub struct RasterPipeline {
+    pipeline: wgpu::RenderPipeline,
+    uniform_buffer: wgpu::Buffer,
+    palette_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    depth_view: wgpu::TextureView,
+    /// `None` for chunks that meshed to nothing
+    chunks: HashMap<ChunkPos, Option<ChunkMesh>>,
+}
+
+impl RasterPipeline {
+    pub fn new(
+        device: &Device,
+        format: wgpu::TextureFormat,
+        width: u32,
+        height: u32,
+    ) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Raster Shader"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("../../assets/shaders/raster.wgsl"))),
+        });
+
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Raster Uniforms"),
+            contents: bytemuck::cast_slice(&[RasterUniforms {
+                view_proj: glam::Mat4::IDENTITY.to_cols_array_2d(),
+                light_dir: [0.4, 0.8, 0.3, 0.0],
+            }]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let palette_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Material Palette"),
+            contents: bytemuck::cast_slice(&[MaterialPalette::default()]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Raster Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Raster Bind Group"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: palette_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Raster Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        const INSTANCE_ATTRIBUTES: [wgpu::VertexAttribute; 1] = wgpu::vertex_attr_array![4 => Float32x3];
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Raster Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                compilation_options: Default::default(),
+                buffers: &[
+                    Mesh::vertex_layout(),
+                    wgpu::VertexBufferLayout {
+                        array_stride: std::mem::size_of::<ChunkInstance>() as wgpu::BufferAddress,
+                        step_mode: wgpu::VertexStepMode::Instance,
+                        attributes: &INSTANCE_ATTRIBUTES,
+                    },
+                ],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                compilation_options: Default::default(),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                cull_mode: Some(wgpu::Face::Back),
+                ..Default::default()
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: Default::default(),
+                bias: Default::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+
+        Self {
+            pipeline,
+            uniform_buffer,
+            palette_buffer,
+            bind_group,
+            depth_view: Self::create_depth_view(device, width, height),
+            chunks: HashMap::new(),
+        }
+    }
+
+    pub fn set_palette(&self, queue: &Queue, palette: &MaterialPalette) {
+        queue.write_buffer(&self.palette_buffer, 0, bytemuck::cast_slice(&[*palette]));
+    }
+
+    /// replace the mesh drawn for a chunk
+    pub fn upload_chunk(&mut self, device: &Device, pos: ChunkPos, data: &MeshData) {
+        if data.is_empty() {
+            self.chunks.insert(pos, None);
+            return;
+        }
+
+        let size = Chunk::SIZE as f32;
+        let instance = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Chunk Instance"),
+            contents: bytemuck::cast_slice(&[ChunkInstance {
+                origin: [pos.x as f32 * size, pos.y as f32 * size, pos.z as f32 * size],
+            }]),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+
+        self.chunks.insert(pos, Some(ChunkMesh {
+            mesh: Mesh::from_data(device, data, Some("Chunk Mesh")),
+            instance,
+        }));
+    }
+
+    pub fn remove_chunk(&mut self, pos: ChunkPos) {
+        self.chunks.remove(&pos);
+    }
+
+    pub fn has_chunk(&self, pos: ChunkPos) -> bool {
+        self.chunks.contains_key(&pos)
+    }
+
+    /// chunks that have been meshed, empty or not
+    pub fn chunk_positions(&self) -> impl Iterator<Item = ChunkPos> + '_ {
+        self.chunks.keys().copied()
+    }
+
+    pub fn resize(&mut self, device: &Device, width: u32, height: u32) {
+        self.depth_view = Self::create_depth_view(device, width, height);
+    }
+
+    pub fn render(
+        &self,
+        device: &Device,
+        queue: &Queue,
+        camera: &Camera,
+        output_texture: &wgpu::Texture,
+    ) {
+        let view_proj = camera.build_view_projection_matrix();
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[RasterUniforms {
+            view_proj,
+            light_dir: [0.4, 0.8, 0.3, 0.0],
+        }]));
+
+        let frustum = Frustum::from_matrix(&Matrix(glam::Mat4::from_cols_array_2d(&view_proj)));
+        let size = Chunk::SIZE as f32;
+
+        let output_view = output_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Raster Encoder"),
+        });
+
+        {
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Raster Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &output_view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color { r: 0.6, g: 0.7, b: 0.8, a: 1.0 }),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &self.depth_view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: wgpu::StoreOp::Discard,
+                    }),
+                    stencil_ops: None,
+                }),
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+
+            pass.set_pipeline(&self.pipeline);
+            pass.set_bind_group(0, &self.bind_group, &[]);
+
+            for (pos, chunk) in self.chunks.iter().filter_map(|(pos, chunk)| Some((pos, chunk.as_ref()?))) {
+                let min = Vec3f(pos.x as f32 * size, pos.y as f32 * size, pos.z as f32 * size);
+                let max = Vec3f(min.0 + size, min.1 + size, min.2 + size);
+                if !frustum.intersects_aabb(min, max) {
+                    continue;
+                }
+
+                pass.set_vertex_buffer(0, chunk.mesh.vertex_buffer.slice(..));
+                pass.set_vertex_buffer(1, chunk.instance.slice(..));
+                pass.set_index_buffer(chunk.mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+                pass.draw_indexed(0..chunk.mesh.num_elements, 0, 0..1);
+            }
+        }
+
+        queue.submit(Some(encoder.finish()));
+    }
+
+    fn create_depth_view(device: &Device, width: u32, height: u32) -> wgpu::TextureView {
+        device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Raster Depth Texture"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: DEPTH_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        })
+        .create_view(&wgpu::TextureViewDescriptor::default())
+    }
+}
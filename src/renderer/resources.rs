@@ -1,5 +1,8 @@
 use wgpu::{Instance, Surface, Device, Queue, Adapter};
+use wgpu::util::DeviceExt;
 use crate::window::EngineWindow;
+use crate::world::{MeshData, MeshVertex};
+use super::profiler::GpuProfiler;
 
 pub struct GPUResources {
     pub surface: Surface,
@@ -24,12 +27,13 @@ impl GPUResources {
             force_fallback_adapter: false,
         }).await.unwrap();
 
+        // timestamp queries are optional, profiling switches itself off without them
         let (device, queue) = adapter
             .request_device(
                 &wgpu::DeviceDescriptor {
-                    memory_hints,
+                    memory_hints: Default::default(),
                     label: Some("Primary Device"),
-                    required_features: wgpu::Features::empty(),
+                    required_features: GpuProfiler::features(&adapter),
                     required_limits: wgpu::Limits::default(),
                 },
                 None
@@ -53,6 +57,44 @@ pub struct Mesh {
     pub num_elements: u32,
 }
 
+impl Mesh {
+    /// upload cpu mesh data, e.g. from the chunk mesher
+    pub fn from_data(device: &Device, data: &MeshData, label: Option<&str>) -> Self {
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label,
+            contents: bytemuck::cast_slice(&data.vertices),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label,
+            contents: bytemuck::cast_slice(&data.indices),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+
+        Self {
+            vertex_buffer,
+            index_buffer,
+            num_elements: data.indices.len() as u32,
+        }
+    }
+
+    /// layout of `MeshVertex`: position, normal, block id, ao
+    pub fn vertex_layout() -> wgpu::VertexBufferLayout<'static> {
+        const ATTRIBUTES: [wgpu::VertexAttribute; 4] = wgpu::vertex_attr_array![
+            0 => Float32x3,
+            1 => Float32x3,
+            2 => Uint32,
+            3 => Float32,
+        ];
+
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<MeshVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &ATTRIBUTES,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Texture {
     pub texture: wgpu::Texture,
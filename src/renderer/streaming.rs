@@ -0,0 +1,373 @@
+use std::collections::HashMap;
+use wgpu::{Device, Queue};
+use crate::utils::math::{Frustum, Vec3f};
+use crate::world::{Chunk, ChunkPos, SparseVoxelOctree, World};
+
+/// chunks per axis in the indirection window around the camera
+pub const GRID_SIZE: u32 = 16;
+/// marks an indirection cell with no resident chunk
+pub const EMPTY_SLOT: u32 = u32::MAX;
+/// marks an indirection cell whose chunk was evicted; the shader reads its fallback instead
+pub const FALLBACK_SLOT: u32 = u32::MAX - 1;
+/// words of block data per slot: full resolution then each mip, packed four to a word
+const BLOCK_WORDS: u64 = (Chunk::VOLUME + Chunk::VOLUME / 8 + Chunk::VOLUME / 64 + Chunk::VOLUME / 512) as u64 / 4;
+/// words of occupancy per slot: a bit per voxel, then the 4³ and 8³ summaries
+const OCCUPANCY_WORDS: u64 = (Chunk::SIZE * Chunk::SIZE) as u64 + 16 + 2;
+/// words of distance field per slot: eight 4-bit distances to a word, then the metric
+const DISTANCE_WORDS: u64 = Chunk::VOLUME as u64 / 8 + 1;
+/// u32 words per chunk slot: block data, the chunk's encoded octree, its
+/// occupancy masks, then its distance field
+pub const SLOT_WORDS: u64 = BLOCK_WORDS + SparseVoxelOctree::GPU_WORDS as u64 + OCCUPANCY_WORDS + DISTANCE_WORDS;
+/// gpu bytes one resident chunk costs
+pub const SLOT_BYTES: u64 = SLOT_WORDS * 4;
+
+/// origin xyz and grid size, ahead of the cells
+const HEADER_WORDS: usize = 4;
+/// evicted chunks brought back per frame, spreads re-uploads out
+const MAX_RESTORES_PER_FRAME: usize = 8;
+/// voxels per side of a fallback cell
+const FALLBACK_CELL: usize = 8;
+
+/// what an evicted chunk renders as: a 4³ occupancy mask of 8³ cells in a single block
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+struct Fallback {
+    mask: [u32; 2],
+    block: u32,
+}
+
+impl Fallback {
+    /// cells at least half solid become solid, drawn in the chunk's most common block
+    fn from_chunk(chunk: &Chunk) -> Self {
+        let cells = Chunk::SIZE / FALLBACK_CELL;
+        let mut counts = [0u32; 256];
+        let mut mask = [0u32; 2];
+
+        for cz in 0..cells {
+            for cy in 0..cells {
+                for cx in 0..cells {
+                    let mut solid = 0;
+                    for z in cz * FALLBACK_CELL..(cz + 1) * FALLBACK_CELL {
+                        for y in cy * FALLBACK_CELL..(cy + 1) * FALLBACK_CELL {
+                            for x in cx * FALLBACK_CELL..(cx + 1) * FALLBACK_CELL {
+                                let block = chunk.get_block(x, y, z);
+                                if block != 0 {
+                                    solid += 1;
+                                    counts[block as usize] += 1;
+                                }
+                            }
+                        }
+                    }
+                    if solid * 2 >= FALLBACK_CELL.pow(3) {
+                        let bit = (cz * cells + cy) * cells + cx;
+                        mask[bit / 32] |= 1 << (bit % 32);
+                    }
+                }
+            }
+        }
+
+        let block = (1..256).max_by_key(|&b| counts[b]).unwrap_or(1) as u32;
+        Self { mask, block }
+    }
+}
+
+struct Resident {
+    slot: u32,
+    last_visible: u64,
+}
+
+/// gpu-resident chunk storage: fixed-size slots in one big storage buffer,
+/// plus an indirection table the shader uses to find a chunk's slot.
+///
+/// resident chunks are kept under a memory budget by evicting whichever was
+/// visible least recently; evicted chunks render from a coarse fallback
+pub struct ChunkPool {
+    blocks: wgpu::Buffer,
+    indirection: wgpu::Buffer,
+    fallbacks: wgpu::Buffer,
+    capacity: u32,
+    budget: u64,
+    free: Vec<u32>,
+    resident: HashMap<ChunkPos, Resident>,
+    evicted: HashMap<ChunkPos, Fallback>,
+    /// header followed by GRID_SIZE³ cells, indexed by chunk position modulo the grid
+    table: Vec<u32>,
+    /// mask lo, mask hi, block, padding per cell
+    fallback_table: Vec<u32>,
+    table_dirty: bool,
+    origin: Option<ChunkPos>,
+    frame: u64,
+}
+
+impl ChunkPool {
+    pub fn new(device: &Device, capacity: u32, budget: u64) -> Self {
+        let cells = GRID_SIZE.pow(3) as usize;
+        let mut table = vec![EMPTY_SLOT; HEADER_WORDS + cells];
+        table[3] = GRID_SIZE;
+        let fallback_table = vec![0; cells * 4];
+
+        Self {
+            blocks: Self::create_blocks(device, capacity),
+            indirection: Self::create_table(device, "Chunk Indirection Table", table.len()),
+            fallbacks: Self::create_table(device, "Chunk Fallback Table", fallback_table.len()),
+            capacity,
+            budget,
+            free: (0..capacity).rev().collect(),
+            resident: HashMap::new(),
+            evicted: HashMap::new(),
+            table,
+            fallback_table,
+            table_dirty: true,
+            origin: None,
+            frame: 0,
+        }
+    }
+
+    pub fn blocks(&self) -> &wgpu::Buffer {
+        &self.blocks
+    }
+
+    pub fn indirection(&self) -> &wgpu::Buffer {
+        &self.indirection
+    }
+
+    pub fn fallbacks(&self) -> &wgpu::Buffer {
+        &self.fallbacks
+    }
+
+    pub fn resident(&self) -> usize {
+        self.resident.len()
+    }
+
+    /// bytes held by resident chunks
+    pub fn memory_used(&self) -> u64 {
+        self.resident.len() as u64 * SLOT_BYTES
+    }
+
+    /// bytes a chunk currently costs on the gpu, zero if evicted or absent
+    pub fn chunk_memory(&self, pos: ChunkPos) -> u64 {
+        if self.resident.contains_key(&pos) { SLOT_BYTES } else { 0 }
+    }
+
+    pub fn set_budget(&mut self, budget: u64) {
+        self.budget = budget.max(SLOT_BYTES);
+    }
+
+    /// upload every loaded chunk, e.g. when the pool is created after the world
+    pub fn upload_all(&mut self, device: &Device, queue: &Queue, world: &World, frustum: &Frustum) -> bool {
+        let loaded = world.loaded_chunks();
+        self.update(device, queue, world, &loaded, frustum)
+    }
+
+    /// upload dirty chunks, free unloaded ones, and rebalance against the
+    /// budget. returns true if the block buffer had to grow, so bind groups
+    /// holding it are stale
+    pub fn update(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        world: &World,
+        dirty: &[ChunkPos],
+        frustum: &Frustum,
+    ) -> bool {
+        self.frame += 1;
+        let mut grown = false;
+
+        for &pos in dirty {
+            if world.is_loaded(pos) {
+                grown |= self.upload(device, queue, world, pos);
+            } else if let Some(resident) = self.resident.remove(&pos) {
+                self.free.push(resident.slot);
+                self.table_dirty = true;
+            } else if self.evicted.remove(&pos).is_some() {
+                self.table_dirty = true;
+            }
+        }
+
+        // bring back the evicted chunks that came into view
+        let size = Chunk::SIZE as f32;
+        let frame = self.frame;
+        let visible = |pos: &ChunkPos| {
+            let min = Vec3f(pos.x as f32 * size, pos.y as f32 * size, pos.z as f32 * size);
+            frustum.intersects_aabb(min, Vec3f(min.0 + size, min.1 + size, min.2 + size))
+        };
+        for (pos, resident) in &mut self.resident {
+            if visible(pos) {
+                resident.last_visible = frame;
+            }
+        }
+        let mut restore: Vec<_> = self.evicted.keys().filter(|pos| visible(pos)).copied().collect();
+        restore.truncate(MAX_RESTORES_PER_FRAME);
+        for pos in restore {
+            if self.can_fit() {
+                grown |= self.upload(device, queue, world, pos);
+            }
+        }
+
+        self.enforce_budget(world);
+
+        if self.table_dirty {
+            self.write_tables(queue);
+        }
+        grown
+    }
+
+    /// recentre the indirection window, rewriting the table if it moved
+    pub fn recenter(&mut self, queue: &Queue, center: ChunkPos) {
+        let half = GRID_SIZE as i32 / 2;
+        let origin = ChunkPos { x: center.x - half, y: center.y - half, z: center.z - half };
+        if self.origin != Some(origin) {
+            self.origin = Some(origin);
+            self.write_tables(queue);
+        }
+    }
+
+    /// room for one more resident chunk, either free or by evicting one that isn't visible
+    fn can_fit(&self) -> bool {
+        self.memory_used() + SLOT_BYTES <= self.budget
+            || self.resident.values().any(|resident| resident.last_visible < self.frame)
+    }
+
+    fn upload(&mut self, device: &Device, queue: &Queue, world: &World, pos: ChunkPos) -> bool {
+        let mut grown = false;
+        if !self.resident.contains_key(&pos) {
+            if self.memory_used() + SLOT_BYTES > self.budget {
+                self.evict_one(world, Some(pos));
+            }
+            if self.free.is_empty() {
+                self.grow(device, queue);
+                grown = true;
+            }
+            let slot = self.free.pop().unwrap();
+            self.resident.insert(pos, Resident { slot, last_visible: self.frame });
+            self.evicted.remove(&pos);
+            self.table_dirty = true;
+        }
+
+        let slot = self.resident[&pos].slot;
+        let base = slot as u64 * SLOT_BYTES;
+        world.with_chunk(pos, |chunk| {
+            queue.write_buffer(&self.blocks, base, chunk.blocks());
+            for level in 1..=Chunk::MIP_LEVELS {
+                queue.write_buffer(&self.blocks, base + Self::level_offset(level), chunk.mip(level));
+            }
+            let occupancy: Vec<u32> = chunk.occupancy_words().collect();
+            let offset = (BLOCK_WORDS + SparseVoxelOctree::GPU_WORDS as u64) * 4;
+            queue.write_buffer(&self.blocks, base + offset, bytemuck::cast_slice(&occupancy));
+            let offset = offset + OCCUPANCY_WORDS * 4;
+            queue.write_buffer(&self.blocks, base + offset, bytemuck::cast_slice(&chunk.distance_words()));
+        });
+        world.with_octree(pos, |octree| {
+            let words = octree.encode_gpu();
+            queue.write_buffer(&self.blocks, base + BLOCK_WORDS * 4, bytemuck::cast_slice(&words));
+        });
+        grown
+    }
+
+    /// byte offset of a mip level inside a slot, matching the shader
+    fn level_offset(level: usize) -> u64 {
+        (0..level).map(|l| if l == 0 { Chunk::VOLUME } else { Chunk::mip_volume(l) }).sum::<usize>() as u64
+    }
+
+    fn enforce_budget(&mut self, world: &World) {
+        while self.memory_used() > self.budget {
+            if !self.evict_one(world, None) {
+                break;
+            }
+        }
+    }
+
+    /// evict the least recently visible resident chunk other than `keep`
+    fn evict_one(&mut self, world: &World, keep: Option<ChunkPos>) -> bool {
+        let victim = self.resident
+            .iter()
+            .filter(|(pos, _)| Some(**pos) != keep)
+            .min_by_key(|(_, resident)| resident.last_visible)
+            .map(|(pos, _)| *pos);
+        let Some(pos) = victim else {
+            return false;
+        };
+
+        let resident = self.resident.remove(&pos).unwrap();
+        self.free.push(resident.slot);
+        if let Some(fallback) = world.with_chunk(pos, Fallback::from_chunk) {
+            self.evicted.insert(pos, fallback);
+        }
+        self.table_dirty = true;
+        true
+    }
+
+    fn write_tables(&mut self, queue: &Queue) {
+        let Some(origin) = self.origin else {
+            return;
+        };
+        self.table_dirty = false;
+
+        self.table[0] = origin.x as u32;
+        self.table[1] = origin.y as u32;
+        self.table[2] = origin.z as u32;
+        self.table[HEADER_WORDS..].fill(EMPTY_SLOT);
+
+        let grid = GRID_SIZE as i32;
+        let in_window = |pos: &ChunkPos| {
+            let (dx, dy, dz) = (pos.x - origin.x, pos.y - origin.y, pos.z - origin.z);
+            (0..grid).contains(&dx) && (0..grid).contains(&dy) && (0..grid).contains(&dz)
+        };
+
+        for (pos, resident) in self.resident.iter().filter(|(pos, _)| in_window(pos)) {
+            self.table[HEADER_WORDS + Self::cell(*pos)] = resident.slot;
+        }
+        for (pos, fallback) in self.evicted.iter().filter(|(pos, _)| in_window(pos)) {
+            let cell = Self::cell(*pos);
+            self.table[HEADER_WORDS + cell] = FALLBACK_SLOT;
+            let Fallback { mask, block } = *fallback;
+            self.fallback_table[cell * 4..cell * 4 + 3].copy_from_slice(&[mask[0], mask[1], block]);
+        }
+
+        queue.write_buffer(&self.indirection, 0, bytemuck::cast_slice(&self.table));
+        queue.write_buffer(&self.fallbacks, 0, bytemuck::cast_slice(&self.fallback_table));
+    }
+
+    /// toroidal cell index, matching the shader
+    fn cell(pos: ChunkPos) -> usize {
+        let grid = GRID_SIZE as i32;
+        let (x, y, z) = (pos.x.rem_euclid(grid), pos.y.rem_euclid(grid), pos.z.rem_euclid(grid));
+        ((z * grid + y) * grid + x) as usize
+    }
+
+    /// double the slot count, carrying resident chunks over on the gpu
+    fn grow(&mut self, device: &Device, queue: &Queue) {
+        let capacity = self.capacity * 2;
+        let blocks = Self::create_blocks(device, capacity);
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Chunk Pool Grow Encoder"),
+        });
+        encoder.copy_buffer_to_buffer(&self.blocks, 0, &blocks, 0, self.capacity as u64 * SLOT_BYTES);
+        queue.submit(Some(encoder.finish()));
+
+        log::debug!("chunk pool grown to {capacity} slots");
+        self.free.extend((self.capacity..capacity).rev());
+        self.blocks = blocks;
+        self.capacity = capacity;
+    }
+
+    fn create_blocks(device: &Device, capacity: u32) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Chunk Pool Blocks"),
+            size: capacity as u64 * SLOT_BYTES,
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_DST
+                | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        })
+    }
+
+    fn create_table(device: &Device, label: &str, words: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(label),
+            size: (words * 4) as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+}
@@ -56,3 +56,45 @@ pub fn project_to_ndc(pos: Vec3f, view_proj: &Matrix) -> Vec3f {
     let pos = view_proj.0.project_point3(pos.to_glam());
     Vec3f::from_glam(pos)
 }
+
+// low-discrepancy halton sequence in [0, 1), used for sub-pixel jitter
+pub fn halton(mut index: u32, base: u32) -> f32 {
+    let mut result = 0.0;
+    let mut f = 1.0;
+    while index > 0 {
+        f /= base as f32;
+        result += f * (index % base) as f32;
+        index /= base;
+    }
+    result
+}
+
+// view frustum as six inward-facing planes (xyz normal, w distance)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Frustum {
+    planes: [Vec4; 6],
+}
+
+impl Frustum {
+    // extract planes from a view-projection matrix with 0..1 clip depth
+    pub fn from_matrix(view_proj: &Matrix) -> Self {
+        let m = view_proj.0;
+        let (r0, r1, r2, r3) = (m.row(0), m.row(1), m.row(2), m.row(3));
+        let planes = [r3 + r0, r3 - r0, r3 + r1, r3 - r1, r2, r3 - r2]
+            .map(|p| p / p.truncate().length());
+        Self { planes }
+    }
+
+    // conservative test, may keep boxes that sit just outside a corner
+    pub fn intersects_aabb(&self, min: Vec3f, max: Vec3f) -> bool {
+        self.planes.iter().all(|plane| {
+            // the box corner furthest along the plane normal
+            let p = Vec3::new(
+                if plane.x >= 0.0 { max.0 } else { min.0 },
+                if plane.y >= 0.0 { max.1 } else { min.1 },
+                if plane.z >= 0.0 { max.2 } else { min.2 },
+            );
+            plane.truncate().dot(p) + plane.w >= 0.0
+        })
+    }
+}
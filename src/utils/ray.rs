@@ -1,4 +1,4 @@
-use crate::world::Chunk;
+use crate::world::{Brickmap, Chunk, BRICK_SIZE};
 use crate::utils::math::{Vec3f, Matrix};
 use egui::Direction;
 use glam::Vec3;
@@ -30,88 +30,128 @@ impl Ray {
         )
     }
 
-    // using the infamous 3D-DDA ray traversal algorithm
-    // https://www.researchgate.net/publication/233899848
+    // march through a chunk in chunk-local space. the occupancy masks answer
+    // "is anything solid here" for 8³ and 4³ cells and single voxels, so the
+    // ray jumps over the largest empty cell it is in instead of testing air
+    // voxels one by one
     pub fn march(&self, chunk: &Chunk) -> Option<RaycastHit> {
-        let mut t = 0.0;
-        let step = Vec3f(
-            self.direction.0.signum(),
-            self.direction.1.signum(),
-            self.direction.2.signum(),
-        );
-
-        let mut voxel_pos = self.origin.floor();
-        let mut t_max = Self::calc_t_max(self.origin, self.direction, step);
-        let mut t_delta = Self::calc_t_delta(self.direction);
-
-        while t < self.distance {
-            if let Some(block) = chunk.get_block(
-                voxel_pos.0 as i32,
-                voxel_pos.1 as i32,
-                voxel_pos.2 as i32,
-            ) {
-                if block != 0 {
-                    return Some(RaycastHit {
-                        position: self.at(t),
-                        normal: self.calculate_normal(voxel_pos),
-                        distance: t,
-                        voxel: block,
-                    });
-                }
-            }
-
-            let (axis, min_t) = Self::find_next_axis(t_max);
-            t = min_t;
-            voxel_pos = Self::step_voxel(voxel_pos, axis, step);
-            t_max.0 += t_delta.0 * (axis == 0) as i32 as f32;
-            t_max.1 += t_delta.1 * (axis == 1) as i32 as f32;
-            t_max.2 += t_delta.2 * (axis == 2) as i32 as f32;
+        self.march_steps(chunk).0
+    }
+
+    // `march` and the number of cells it stepped through
+    fn march_steps(&self, chunk: &Chunk) -> (Option<RaycastHit>, u32) {
+        let origin = self.origin.to_glam();
+        let dir = self.direction.to_glam();
+        let inv = dir.recip();
+
+        let size = Chunk::SIZE as f32;
+        let (t0, t1) = ((Vec3::ZERO - origin) * inv, (Vec3::splat(size) - origin) * inv);
+        let (near, far) = (t0.min(t1), t0.max(t1));
+        let mut t = near.max_element().max(0.0);
+        let end = far.min_element().min(self.distance);
+        let mut normal = if near.max_element() > 0.0 { face_normal(near, dir) } else { Vec3::ZERO };
+        let mut steps = 0;
+
+        while t < end {
+            steps += 1;
+            let p = (origin + dir * (t + 1e-4)).clamp(Vec3::ZERO, Vec3::splat(size - 1.0));
+            let (x, y, z) = (p.x as usize, p.y as usize, p.z as usize);
+
+            let cell = if !chunk.cell_8_occupied(x / 8, y / 8, z / 8) {
+                8
+            } else if !chunk.cell_4_occupied(x / 4, y / 4, z / 4) {
+                4
+            } else if !chunk.is_solid(x, y, z) {
+                1
+            } else {
+                let hit = RaycastHit {
+                    position: Vec3f::from_glam(origin + dir * t),
+                    normal: Vec3f::from_glam(normal),
+                    distance: t,
+                    voxel: chunk.get_block(x, y, z),
+                };
+                return (Some(hit), steps);
+            };
+
+            let corner = Vec3::new((x / cell * cell) as f32, (y / cell * cell) as f32, (z / cell * cell) as f32);
+            let exits = exit_times(origin, dir, corner, cell as f32);
+            t = exits.min_element().max(t + 1e-4);
+            normal = face_normal(-exits, dir);
         }
 
-        None
+        (None, steps)
     }
 
-    // helper functions
-    fn calc_t_max(origin: Vec3f, dir: Vec3f, step: Vec3f) -> Vec3f {
-        Vec3f(
-            (step.0 - (origin.0 % 1.0)) / dir.0,
-            (step.1 - (origin.1 % 1.0)) / dir.1,
-            (step.2 - (origin.2 % 1.0)) / dir.2,
-        )
+    // two-level traversal of a brickmap: a brick the grid marks empty is
+    // crossed in one jump, occupied bricks are walked voxel by voxel
+    pub fn march_brickmap(&self, map: &Brickmap) -> VoxelRayResult {
+        self.march_brickmap_steps(map).0
     }
 
-    fn calc_t_delta(dir: Vec3f) -> Vec3f {
-        Vec3f(1.0 / dir.0.abs(), 1.0 / dir.1.abs(), 1.0 / dir.2.abs())
-    }
+    // `march_brickmap` and the number of bricks and voxels it stepped through
+    fn march_brickmap_steps(&self, map: &Brickmap) -> (VoxelRayResult, u32) {
+        let origin = self.origin.to_glam();
+        let dir = self.direction.to_glam();
+        let inv = dir.recip();
 
-    fn find_next_axis(t_max: Vec3f) -> (usize, f32) {
-        let axis = if t_max.0 < t_max.1 && t_max.0 < t_max.2 {
-            0
-        } else if t_max.1 < t_max.2 {
-            1
-        } else {
-            2
-        };
-        (axis, t_max[axis])
-    }
+        // only the part of the ray inside the map can hit anything
+        let lo = (map.origin() * BRICK_SIZE as i32).as_vec3();
+        let hi = lo + (map.dims() * BRICK_SIZE as u32).as_vec3();
+        let (t0, t1) = ((lo - origin) * inv, (hi - origin) * inv);
+        let (near, far) = (t0.min(t1), t0.max(t1));
+        let mut t = near.max_element().max(0.0);
+        let end = far.min_element().min(self.distance);
+        let mut normal = if near.max_element() > 0.0 { face_normal(near, dir) } else { Vec3::ZERO };
+        let mut steps = 0;
+
+        while t < end {
+            steps += 1;
+            let voxel = (origin + dir * (t + 1e-4)).floor().as_ivec3();
+            let (corner, size) = match map.brick_at(voxel) {
+                None => (Brickmap::brick_coords(voxel) * BRICK_SIZE as i32, BRICK_SIZE as f32),
+                Some(_) => {
+                    let block = map.get_block(voxel.x, voxel.y, voxel.z);
+                    if block != 0 {
+                        let hit = RaycastHit {
+                            position: Vec3f::from_glam(origin + dir * t),
+                            normal: Vec3f::from_glam(normal),
+                            distance: t,
+                            voxel: block,
+                        };
+                        return (Some(hit), steps);
+                    }
+                    (voxel, 1.0)
+                }
+            };
 
-    fn step_voxel(mut pos: Vec3f, axis: usize, step: Vec3f) -> Vec3f {
-        match axis {
-            0 => pos.0 += step.0,
-            1 => pos.1 += step.1,
-            2 => pos.2 += step.2,
-            _ => unreachable!(),
+            let exits = exit_times(origin, dir, corner.as_vec3(), size);
+            t = exits.min_element().max(t + 1e-4);
+            normal = face_normal(-exits, dir);
         }
-        pos
+
+        (None, steps)
     }
 
-    fn calculate_normal(&self, voxel_pos: Vec3f) -> Vec3f {
-        let delta = self.at(0.001).floor() - voxel_pos;
-        Vec3f(
-            -delta.0.signum(),
-            -delta.1.signum(),
-            -delta.2.signum(),
-        )
+}
+
+// t at which a ray leaves the cube `corner..corner + size` on each axis. an
+// axis the ray runs parallel to never ends it, so that axis is infinite
+// rather than the -inf or nan `0 * inf` gives, which would stall the march
+// at one epsilon a step. the shaders mask the same way
+pub(crate) fn exit_times(origin: Vec3, dir: Vec3, corner: Vec3, size: f32) -> Vec3 {
+    let far_face = Vec3::select(dir.cmpgt(Vec3::ZERO), corner + size, corner);
+    Vec3::select(dir.cmpeq(Vec3::ZERO), Vec3::INFINITY, (far_face - origin) * dir.recip())
+}
+
+// normal of the face on the axis with the largest t. pass `-exit_times` for
+// the face crossed on the way out of a cell
+pub(crate) fn face_normal(t: Vec3, dir: Vec3) -> Vec3 {
+    if t.x >= t.y && t.x >= t.z {
+        Vec3::new(-dir.x.signum(), 0.0, 0.0)
+    } else if t.y >= t.z {
+        Vec3::new(0.0, -dir.y.signum(), 0.0)
+    } else {
+        Vec3::new(0.0, 0.0, -dir.z.signum())
     }
 }
 
@@ -125,3 +165,57 @@ pub struct RaycastHit {
 }
 
 pub type VoxelRayResult = Option<RaycastHit>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{ChunkPos, Region};
+
+    fn pillar_base() -> Chunk {
+        let mut chunk = Chunk::new();
+        chunk.set_block(5, 0, 5, 1);
+        chunk
+    }
+
+    #[test]
+    fn axis_aligned_rays_skip_empty_cells() {
+        let ray = Ray::new(Vec3f(5.5, 31.5, 5.5), Vec3f(0.0, -1.0, 0.0));
+        let (hit, steps) = ray.march_steps(&pillar_base());
+        let hit = hit.expect("ray should hit the block");
+        assert!((hit.distance - 30.5).abs() < 1e-3);
+        assert_eq!(hit.normal, Vec3f(0.0, 1.0, 0.0));
+        // three 8³ cells, one 4³ cell and three voxels of air
+        assert!(steps <= 12, "{steps} steps");
+    }
+
+    #[test]
+    fn axis_aligned_misses_end() {
+        let ray = Ray::new(Vec3f(0.5, 0.5, 0.5), Vec3f(0.0, 0.0, 1.0));
+        let (hit, steps) = ray.march_steps(&pillar_base());
+        assert!(hit.is_none());
+        assert!(steps <= 8, "{steps} steps");
+    }
+
+    #[test]
+    fn axis_aligned_rays_cross_empty_bricks() {
+        let mut region = Region::new();
+        region.insert(ChunkPos { x: 0, y: 0, z: 0 }, pillar_base());
+        region.insert(ChunkPos { x: 0, y: 1, z: 0 }, Chunk::new());
+        let map = Brickmap::from_region(&region);
+
+        let ray = Ray::new(Vec3f(5.5, 63.5, 5.5), Vec3f(0.0, -1.0, 0.0));
+        let (hit, steps) = ray.march_brickmap_steps(&map);
+        let hit = hit.expect("ray should hit the block");
+        assert!((hit.distance - 62.5).abs() < 1e-3);
+        assert_eq!(hit.normal, Vec3f(0.0, 1.0, 0.0));
+        // seven empty bricks, then the voxels of the occupied one
+        assert!(steps <= 16, "{steps} steps");
+    }
+
+    #[test]
+    fn exits_ignore_parallel_axes() {
+        let exits = exit_times(Vec3::new(0.5, 7.5, 0.5), Vec3::NEG_Y, Vec3::ZERO, 8.0);
+        assert_eq!(exits, Vec3::new(f32::INFINITY, 7.5, f32::INFINITY));
+        assert_eq!(face_normal(-exits, Vec3::NEG_Y), Vec3::Y);
+    }
+}
@@ -0,0 +1,18 @@
+//! block ids the built-in generators place. ids are just palette indices, so
+//! anything else can use the rest of the range however it likes
+
+pub const AIR: u8 = 0;
+pub const STONE: u8 = 1;
+pub const DIRT: u8 = 2;
+pub const GRASS: u8 = 3;
+pub const SAND: u8 = 4;
+pub const SNOW: u8 = 5;
+pub const WATER: u8 = 6;
+pub const LOG: u8 = 7;
+pub const LEAVES: u8 = 8;
+pub const GRAVEL: u8 = 9;
+pub const TALL_GRASS: u8 = 10;
+pub const CACTUS: u8 = 11;
+pub const FLOWER: u8 = 12;
+pub const COAL_ORE: u8 = 13;
+pub const IRON_ORE: u8 = 14;
@@ -0,0 +1,199 @@
+use glam::{IVec3, UVec3};
+use super::{Chunk, ChunkPos, Region};
+
+/// voxels per side of a brick
+pub const BRICK_SIZE: usize = 8;
+pub const BRICK_VOLUME: usize = BRICK_SIZE * BRICK_SIZE * BRICK_SIZE;
+/// grid cell whose brick would be all air, so none is stored
+pub const EMPTY_BRICK: u32 = u32::MAX;
+
+#[derive(Debug, Clone)]
+struct Brick {
+    blocks: [u8; BRICK_VOLUME],
+    /// solid voxels, the brick is released when this drops to zero
+    solid: u16,
+}
+
+/// two-level world representation: a coarse grid over a fixed box, each cell
+/// pointing at an 8³ brick or `EMPTY_BRICK`. rays skip empty cells whole, and
+/// edits only ever touch one brick
+#[derive(Debug, Clone)]
+pub struct Brickmap {
+    /// low corner of the grid, in bricks
+    origin: IVec3,
+    /// grid cells per axis
+    dims: UVec3,
+    grid: Vec<u32>,
+    bricks: Vec<Brick>,
+    free: Vec<u32>,
+    /// bricks changed since `take_dirty`, for partial gpu uploads
+    dirty: Vec<u32>,
+    grid_dirty: bool,
+}
+
+impl Brickmap {
+    /// an empty map covering `dims` bricks from `origin`, both in bricks
+    pub fn new(origin: IVec3, dims: UVec3) -> Self {
+        Self {
+            origin,
+            dims,
+            grid: vec![EMPTY_BRICK; (dims.x * dims.y * dims.z) as usize],
+            bricks: Vec::new(),
+            free: Vec::new(),
+            dirty: Vec::new(),
+            grid_dirty: true,
+        }
+    }
+
+    /// a map just big enough for every chunk of a region
+    pub fn from_region(region: &Region) -> Self {
+        let Some((min, max)) = region.bounds() else {
+            return Self::new(IVec3::ZERO, UVec3::ZERO);
+        };
+
+        let per_chunk = (Chunk::SIZE / BRICK_SIZE) as i32;
+        let min = IVec3::new(min.x, min.y, min.z);
+        let max = IVec3::new(max.x, max.y, max.z);
+        let mut map = Self::new(min * per_chunk, ((max - min + 1) * per_chunk).as_uvec3());
+        for (pos, chunk) in region.chunks() {
+            map.insert_chunk(pos, chunk);
+        }
+        map
+    }
+
+    pub fn origin(&self) -> IVec3 {
+        self.origin
+    }
+
+    pub fn dims(&self) -> UVec3 {
+        self.dims
+    }
+
+    /// bricks in use
+    pub fn brick_count(&self) -> usize {
+        self.bricks.len() - self.free.len()
+    }
+
+    /// copy a chunk's voxels in, overwriting what was there
+    pub fn insert_chunk(&mut self, pos: ChunkPos, chunk: &Chunk) {
+        let base = IVec3::new(pos.x, pos.y, pos.z) * Chunk::SIZE as i32;
+        for z in 0..Chunk::SIZE {
+            for y in 0..Chunk::SIZE {
+                for x in 0..Chunk::SIZE {
+                    let v = base + IVec3::new(x as i32, y as i32, z as i32);
+                    self.set_block(v.x, v.y, v.z, chunk.get_block(x, y, z));
+                }
+            }
+        }
+    }
+
+    /// block at world coords, air outside the map
+    pub fn get_block(&self, x: i32, y: i32, z: i32) -> u8 {
+        let v = IVec3::new(x, y, z);
+        match self.brick_at(v) {
+            Some(brick) => self.bricks[brick as usize].blocks[Self::voxel_index(v)],
+            None => 0,
+        }
+    }
+
+    /// set a block at world coords, false if it falls outside the map
+    pub fn set_block(&mut self, x: i32, y: i32, z: i32, block: u8) -> bool {
+        let v = IVec3::new(x, y, z);
+        let Some(cell) = self.cell_index(Self::brick_coords(v)) else {
+            return false;
+        };
+
+        let mut brick = self.grid[cell];
+        if brick == EMPTY_BRICK {
+            if block == 0 {
+                return true;
+            }
+            brick = self.allocate();
+            self.grid[cell] = brick;
+            self.grid_dirty = true;
+        }
+
+        let entry = &mut self.bricks[brick as usize];
+        let voxel = &mut entry.blocks[Self::voxel_index(v)];
+        if *voxel == block {
+            return true;
+        }
+        match (*voxel != 0, block != 0) {
+            (false, true) => entry.solid += 1,
+            (true, false) => entry.solid -= 1,
+            _ => {}
+        }
+        *voxel = block;
+
+        if entry.solid == 0 {
+            self.grid[cell] = EMPTY_BRICK;
+            self.free.push(brick);
+            self.grid_dirty = true;
+        } else {
+            self.dirty.push(brick);
+        }
+        true
+    }
+
+    /// brick index holding a world voxel, `None` for air or outside the map
+    pub fn brick_at(&self, v: IVec3) -> Option<u32> {
+        let cell = self.cell_index(Self::brick_coords(v))?;
+        Some(self.grid[cell]).filter(|&brick| brick != EMPTY_BRICK)
+    }
+
+    /// grid cell a brick coordinate falls in, `None` outside the map
+    pub fn cell_index(&self, brick: IVec3) -> Option<usize> {
+        let rel = brick - self.origin;
+        if rel.cmplt(IVec3::ZERO).any() || rel.as_uvec3().cmpge(self.dims).any() {
+            return None;
+        }
+        let rel = rel.as_uvec3();
+        Some(((rel.z * self.dims.y + rel.y) * self.dims.x + rel.x) as usize)
+    }
+
+    pub fn brick_coords(v: IVec3) -> IVec3 {
+        v.div_euclid(IVec3::splat(BRICK_SIZE as i32))
+    }
+
+    /// origin, padding and dims, then one brick index per cell, as the shader reads it
+    pub fn encode_grid(&self) -> Vec<u32> {
+        let mut words = vec![
+            self.origin.x as u32, self.origin.y as u32, self.origin.z as u32, 0,
+            self.dims.x, self.dims.y, self.dims.z,
+        ];
+        words.extend_from_slice(&self.grid);
+        words
+    }
+
+    /// a brick's voxels, x fastest then y then z
+    pub fn brick_bytes(&self, brick: u32) -> &[u8] {
+        &self.bricks[brick as usize].blocks
+    }
+
+    /// slots allocated so far, free or not. gpu storage needs this many bricks
+    pub fn brick_capacity(&self) -> usize {
+        self.bricks.len()
+    }
+
+    /// bricks edited since the last call and whether the grid changed
+    pub fn take_dirty(&mut self) -> (Vec<u32>, bool) {
+        let mut dirty = std::mem::take(&mut self.dirty);
+        dirty.sort_unstable();
+        dirty.dedup();
+        (dirty, std::mem::replace(&mut self.grid_dirty, false))
+    }
+
+    fn allocate(&mut self) -> u32 {
+        if let Some(brick) = self.free.pop() {
+            self.bricks[brick as usize] = Brick { blocks: [0; BRICK_VOLUME], solid: 0 };
+            return brick;
+        }
+        self.bricks.push(Brick { blocks: [0; BRICK_VOLUME], solid: 0 });
+        (self.bricks.len() - 1) as u32
+    }
+
+    fn voxel_index(v: IVec3) -> usize {
+        let local = v.rem_euclid(IVec3::splat(BRICK_SIZE as i32)).as_uvec3();
+        ((local.z as usize * BRICK_SIZE) + local.y as usize) * BRICK_SIZE + local.x as usize
+    }
+}
@@ -1,17 +1,66 @@
+/// how a chunk's distance field measures the way to the nearest solid voxel
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DistanceMetric {
+    /// largest axis offset: everything inside a cube around the voxel is air
+    #[default]
+    Chebyshev,
+    /// sum of axis offsets: everything inside a diamond around the voxel is air
+    Manhattan,
+}
+
 #[derive(Debug, Clone)]
 pub struct Chunk {
     blocks: Vec<u8>,
+    /// downsampled copies of `blocks` at 2×, 4× and 8×, same layout at reduced size
+    mips: [Vec<u8>; Chunk::MIP_LEVELS],
+    /// one bit per voxel, set when solid. word `z * SIZE + y`, bit `x`
+    occupancy: Vec<u32>,
+    /// one bit per 4³ cell with anything solid in it, 8³ cells
+    occupancy_4: [u32; 16],
+    /// one bit per 8³ cell with anything solid in it, 4³ cells
+    occupancy_8: [u32; 2],
+    /// per voxel, distance to the nearest solid voxel in this chunk, capped at `MAX_DISTANCE`
+    distance: Vec<u8>,
+    metric: DistanceMetric,
+    /// set until the first full sweep; edits only patch the field once it is whole
+    distance_stale: bool,
+    /// per voxel sky light, 0 to `MAX_LIGHT`. empty until the chunk is lit
+    sky_light: Vec<u8>,
 }
 
 impl Chunk {
     pub const SIZE: usize = 32;
     pub const VOLUME: usize = Self::SIZE * Self::SIZE * Self::SIZE;
+    /// coarser levels kept besides full resolution
+    pub const MIP_LEVELS: usize = 3;
+    /// distances stop counting here, which bounds how far an edit can reach
+    pub const MAX_DISTANCE: u8 = 15;
+    /// sky light straight under open sky, dropping by one per voxel it spreads
+    pub const MAX_LIGHT: u8 = 15;
 
     /// new empty chunk
     pub fn new() -> Self {
         Self {
             blocks: vec![0; Self::VOLUME],
+            mips: std::array::from_fn(|i| vec![0; Self::mip_volume(i + 1)]),
+            occupancy: vec![0; Self::SIZE * Self::SIZE],
+            occupancy_4: [0; 16],
+            occupancy_8: [0; 2],
+            distance: vec![Self::MAX_DISTANCE; Self::VOLUME],
+            metric: DistanceMetric::default(),
+            distance_stale: true,
+            sky_light: Vec::new(),
+        }
+    }
+
+    /// chunk from raw block ids in `blocks()` order
+    pub fn from_blocks(blocks: &[u8]) -> Self {
+        let mut chunk = Self::new();
+        for (i, &block) in blocks.iter().enumerate().take(Self::VOLUME) {
+            chunk.set_block(i % Self::SIZE, i / Self::SIZE % Self::SIZE, i / (Self::SIZE * Self::SIZE), block);
         }
+        chunk.rebuild_distance_field();
+        chunk
     }
 
     /// get block at local chunk coords
@@ -22,7 +71,313 @@ impl Chunk {
     /// set block at local chunk coords
     pub fn set_block(&mut self, x: usize, y: usize, z: usize, block: u8) {
         let idx = Self::block_index(x, y, z);
+        let old = self.blocks[idx];
+        if old == block {
+            return;
+        }
         self.blocks[idx] = block;
+        if (old != 0) != (block != 0) {
+            self.update_occupancy(x, y, z, block != 0);
+            if !self.distance_stale {
+                self.update_distance(x, y, z, block != 0);
+            }
+        }
+
+        // only the one cell above the edit changes on each level
+        for level in 1..=Self::MIP_LEVELS {
+            let (cx, cy, cz) = (x >> level, y >> level, z >> level);
+            let cell = self.downsample(level, cx, cy, cz);
+            let size = Self::mip_size(level);
+            self.mips[level - 1][(cz * size + cy) * size + cx] = cell;
+        }
+    }
+
+    /// raw block ids in x-fastest, then y, then z order
+    pub fn blocks(&self) -> &[u8] {
+        &self.blocks
+    }
+
+    /// solid without reading the block itself
+    pub fn is_solid(&self, x: usize, y: usize, z: usize) -> bool {
+        (self.occupancy[z * Self::SIZE + y] >> x) & 1 != 0
+    }
+
+    /// whether the 4³ cell at cell coords (each 0..8) holds anything solid
+    pub fn cell_4_occupied(&self, cx: usize, cy: usize, cz: usize) -> bool {
+        let bit = (cz * 8 + cy) * 8 + cx;
+        (self.occupancy_4[bit / 32] >> (bit % 32)) & 1 != 0
+    }
+
+    /// whether the 8³ cell at cell coords (each 0..4) holds anything solid
+    pub fn cell_8_occupied(&self, cx: usize, cy: usize, cz: usize) -> bool {
+        let bit = (cz * 4 + cy) * 4 + cx;
+        (self.occupancy_8[bit / 32] >> (bit % 32)) & 1 != 0
+    }
+
+    /// distance from a voxel to the nearest solid one in this chunk, `None`
+    /// until the field has been built
+    pub fn distance(&self, x: usize, y: usize, z: usize) -> Option<u8> {
+        (!self.distance_stale).then(|| self.distance[Self::block_index(x, y, z)])
+    }
+
+    pub fn distance_metric(&self) -> DistanceMetric {
+        self.metric
+    }
+
+    pub fn set_distance_metric(&mut self, metric: DistanceMetric) {
+        if self.metric != metric {
+            self.metric = metric;
+            self.rebuild_distance_field();
+        }
+    }
+
+    /// recompute the whole distance field. generation writes every voxel, so
+    /// it is cheaper to sweep once afterwards than to patch after each write
+    pub fn rebuild_distance_field(&mut self) {
+        for (distance, &block) in self.distance.iter_mut().zip(&self.blocks) {
+            *distance = if block != 0 { 0 } else { Self::MAX_DISTANCE };
+        }
+        self.sweep([0; 3], [Self::SIZE; 3]);
+        self.distance_stale = false;
+    }
+
+    /// distances packed eight 4-bit values to a word, then the metric, as
+    /// uploaded to the gpu. all zero while stale, which never skips anything
+    pub fn distance_words(&self) -> Vec<u32> {
+        let mut words = vec![0; Self::VOLUME / 8 + 1];
+        if !self.distance_stale {
+            for (i, &distance) in self.distance.iter().enumerate() {
+                words[i / 8] |= (distance as u32) << ((i % 8) * 4);
+            }
+        }
+        words[Self::VOLUME / 8] = self.metric as u32;
+        words
+    }
+
+    /// sky light at a voxel, `None` until the chunk has been lit
+    pub fn sky_light(&self, x: usize, y: usize, z: usize) -> Option<u8> {
+        self.sky_light.get(Self::block_index(x, y, z)).copied()
+    }
+
+    /// flood sky light down from the top face and through air, taking in
+    /// light from lit chunks on every side. `faces` are the chunks across
+    /// each face, -x, +x, -y, +y, -z, +z. a column is open to the sky if full
+    /// sky light reaches the bottom of the chunk above, or if there is no
+    /// chunk above, so a lit chunk above carries the sky of everything over
+    /// it. returns whether any voxel's light changed
+    pub fn rebuild_sky_light(&mut self, faces: [Option<&Chunk>; 6]) -> bool {
+        let mut light = vec![0; Self::VOLUME];
+        let mut queue = std::collections::VecDeque::new();
+
+        for z in 0..Self::SIZE {
+            for x in 0..Self::SIZE {
+                let open = faces[3].is_none_or(|above| above.sky_light(x, 0, z) == Some(Self::MAX_LIGHT));
+                if !open {
+                    continue;
+                }
+                for y in (0..Self::SIZE).rev() {
+                    if self.is_solid(x, y, z) {
+                        break;
+                    }
+                    light[Self::block_index(x, y, z)] = Self::MAX_LIGHT;
+                    queue.push_back([x, y, z]);
+                }
+            }
+        }
+
+        // light leaking in across each face, one dimmer than next door
+        for (face, neighbour) in faces.iter().enumerate() {
+            let Some(neighbour) = neighbour else {
+                continue;
+            };
+            let (axis, positive) = (face / 2, face % 2 == 1);
+            let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+            for j in 0..Self::SIZE {
+                for i in 0..Self::SIZE {
+                    let mut here = [0; 3];
+                    here[axis] = if positive { Self::SIZE - 1 } else { 0 };
+                    here[u] = i;
+                    here[v] = j;
+                    let mut there = here;
+                    there[axis] = Self::SIZE - 1 - here[axis];
+
+                    let spread = neighbour.sky_light(there[0], there[1], there[2]).unwrap_or(0).saturating_sub(1);
+                    let index = Self::block_index(here[0], here[1], here[2]);
+                    if spread > light[index] && !self.is_solid(here[0], here[1], here[2]) {
+                        light[index] = spread;
+                        queue.push_back(here);
+                    }
+                }
+            }
+        }
+
+        while let Some(v) = queue.pop_front() {
+            let spread = light[Self::block_index(v[0], v[1], v[2])].saturating_sub(1);
+            if spread == 0 {
+                continue;
+            }
+            for axis in 0..3 {
+                for step in [-1isize, 1] {
+                    let mut n = v;
+                    n[axis] = match n[axis].checked_add_signed(step) {
+                        Some(c) if c < Self::SIZE => c,
+                        _ => continue,
+                    };
+                    let index = Self::block_index(n[0], n[1], n[2]);
+                    if !self.is_solid(n[0], n[1], n[2]) && light[index] < spread {
+                        light[index] = spread;
+                        queue.push_back(n);
+                    }
+                }
+            }
+        }
+
+        let changed = light != self.sky_light;
+        self.sky_light = light;
+        changed
+    }
+
+    /// per-voxel solid bits, then the 4³ and 8³ summaries, as uploaded to the gpu
+    pub fn occupancy_words(&self) -> impl Iterator<Item = u32> + '_ {
+        self.occupancy.iter().chain(&self.occupancy_4).chain(&self.occupancy_8).copied()
+    }
+
+    /// block ids at mip `level` (1 is 2×, up to `MIP_LEVELS`), laid out like `blocks`
+    pub fn mip(&self, level: usize) -> &[u8] {
+        &self.mips[level - 1]
+    }
+
+    /// cells per side at mip `level`
+    pub fn mip_size(level: usize) -> usize {
+        Self::SIZE >> level
+    }
+
+    /// cells at mip `level`
+    pub fn mip_volume(level: usize) -> usize {
+        Self::mip_size(level).pow(3)
+    }
+
+    /// flip a voxel's bit and refresh the two summary cells above it
+    fn update_occupancy(&mut self, x: usize, y: usize, z: usize, solid: bool) {
+        let word = &mut self.occupancy[z * Self::SIZE + y];
+        if solid {
+            *word |= 1 << x;
+        } else {
+            *word &= !(1 << x);
+        }
+
+        // a 4³ cell is four bits in each of sixteen words
+        let (cx, cy, cz) = (x / 4, y / 4, z / 4);
+        let nibble = 0xf << (cx * 4);
+        let occupied = (cz * 4..cz * 4 + 4)
+            .flat_map(|z| (cy * 4..cy * 4 + 4).map(move |y| z * Self::SIZE + y))
+            .any(|i| self.occupancy[i] & nibble != 0);
+        let bit = (cz * 8 + cy) * 8 + cx;
+        set_bit(&mut self.occupancy_4, bit, occupied);
+
+        // an 8³ cell is the eight 4³ cells inside it
+        let (cx, cy, cz) = (x / 8, y / 8, z / 8);
+        let occupied = (0..8).any(|i| {
+            self.cell_4_occupied(cx * 2 + (i & 1), cy * 2 + ((i >> 1) & 1), cz * 2 + (i >> 2))
+        });
+        let bit = (cz * 4 + cy) * 4 + cx;
+        set_bit(&mut self.occupancy_8, bit, occupied);
+    }
+
+    /// re-sweep the part of the field an edit can reach. a voxel further away
+    /// than the cap never took its distance from the edited one, so the box
+    /// around it is enough; a removal resets that box first since distances
+    /// there may grow
+    fn update_distance(&mut self, x: usize, y: usize, z: usize, solid: bool) {
+        let reach = Self::MAX_DISTANCE as usize + 1;
+        let lo = [x, y, z].map(|v| v.saturating_sub(reach));
+        let hi = [x, y, z].map(|v| (v + reach + 1).min(Self::SIZE));
+
+        if solid {
+            self.distance[Self::block_index(x, y, z)] = 0;
+        } else {
+            for z in lo[2]..hi[2] {
+                for y in lo[1]..hi[1] {
+                    for x in lo[0]..hi[0] {
+                        let i = Self::block_index(x, y, z);
+                        self.distance[i] = if self.blocks[i] != 0 { 0 } else { Self::MAX_DISTANCE };
+                    }
+                }
+            }
+        }
+        self.sweep(lo, hi);
+    }
+
+    /// two-pass chamfer over a box: forward in scan order taking the neighbours
+    /// already visited, then backward taking the rest. neighbours outside the
+    /// box still count, which is what lets a partial sweep stitch into the field
+    fn sweep(&mut self, lo: [usize; 3], hi: [usize; 3]) {
+        const MANHATTAN: [[i32; 3]; 3] = [[-1, 0, 0], [0, -1, 0], [0, 0, -1]];
+        let chebyshev: Vec<[i32; 3]> = (0..27)
+            .map(|i| [i % 3 - 1, i / 3 % 3 - 1, i / 9 - 1])
+            .filter(|&[dx, dy, dz]| dz < 0 || (dz == 0 && (dy < 0 || (dy == 0 && dx < 0))))
+            .collect();
+        let before: &[[i32; 3]] = match self.metric {
+            DistanceMetric::Chebyshev => &chebyshev,
+            DistanceMetric::Manhattan => &MANHATTAN,
+        };
+
+        let size = Self::SIZE as i32;
+        let mut relax = |x: usize, y: usize, z: usize, sign: i32| {
+            let i = Self::block_index(x, y, z);
+            if self.distance[i] == 0 {
+                return;
+            }
+            for &[dx, dy, dz] in before {
+                let (nx, ny, nz) = (x as i32 + dx * sign, y as i32 + dy * sign, z as i32 + dz * sign);
+                if (0..size).contains(&nx) && (0..size).contains(&ny) && (0..size).contains(&nz) {
+                    let n = self.distance[Self::block_index(nx as usize, ny as usize, nz as usize)];
+                    self.distance[i] = self.distance[i].min(n + 1);
+                }
+            }
+        };
+
+        for z in lo[2]..hi[2] {
+            for y in lo[1]..hi[1] {
+                for x in lo[0]..hi[0] {
+                    relax(x, y, z, 1);
+                }
+            }
+        }
+        for z in (lo[2]..hi[2]).rev() {
+            for y in (lo[1]..hi[1]).rev() {
+                for x in (lo[0]..hi[0]).rev() {
+                    relax(x, y, z, -1);
+                }
+            }
+        }
+    }
+
+    /// representative block of a cell from the eight cells below it: air when
+    /// more than half of them are air, otherwise the most common solid block
+    fn downsample(&self, level: usize, x: usize, y: usize, z: usize) -> u8 {
+        let (below, size) = if level == 1 {
+            (&self.blocks, Self::SIZE)
+        } else {
+            (&self.mips[level - 2], Self::mip_size(level - 1))
+        };
+
+        let mut children = [0u8; 8];
+        for (i, child) in children.iter_mut().enumerate() {
+            let (cx, cy, cz) = (x * 2 + (i & 1), y * 2 + ((i >> 1) & 1), z * 2 + (i >> 2));
+            *child = below[(cz * size + cy) * size + cx];
+        }
+
+        let solid = children.iter().filter(|&&b| b != 0).count();
+        if solid < 4 {
+            return 0;
+        }
+        children
+            .iter()
+            .filter(|&&b| b != 0)
+            .max_by_key(|&&b| children.iter().filter(|&&c| c == b).count())
+            .copied()
+            .unwrap_or(0)
     }
 
     /// convert 3D coordinates to 1D index
@@ -30,3 +385,11 @@ impl Chunk {
         z * Self::SIZE * Self::SIZE + y * Self::SIZE + x
     }
 }
+
+fn set_bit(words: &mut [u32], bit: usize, value: bool) {
+    if value {
+        words[bit / 32] |= 1 << (bit % 32);
+    } else {
+        words[bit / 32] &= !(1 << (bit % 32));
+    }
+}
@@ -0,0 +1,389 @@
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use glam::{IVec3, Vec3};
+use crate::utils::ray::{exit_times, face_normal};
+use super::region::{read_u32, Region};
+use super::{Chunk, ChunkPos, OctreeHit, OCTREE_LEAF};
+
+const MAGIC: &[u8; 4] = b"HCDG";
+const VERSION: u32 = 1;
+
+/// nudge past a node boundary so the next lookup lands in the neighbour
+const EPSILON: f32 = 1e-4;
+
+/// sparse voxel dag: an octree over a whole region where identical subtrees
+/// are stored once and shared, so large static scenes repeat almost for free.
+///
+/// words use the gpu octree encoding: `OCTREE_LEAF` plus a block in the low
+/// byte, or the index of eight child words. word 0 is the root. nothing is
+/// ever mixed, the dag goes all the way down to single voxels
+#[derive(Debug, Clone)]
+pub struct SparseVoxelDag {
+    /// world voxel coords of the low corner
+    origin: IVec3,
+    /// voxels per side, a power of two no smaller than a chunk
+    size: u32,
+    words: Vec<u32>,
+}
+
+/// hash-conses interior nodes while the dag is built bottom-up
+struct Builder<'a> {
+    region: &'a Region,
+    words: Vec<u32>,
+    nodes: HashMap<[u32; 8], u32>,
+}
+
+impl SparseVoxelDag {
+    /// bake every chunk of a region into one dag covering its bounding box.
+    /// chunks missing inside the box are air
+    pub fn from_region(region: &Region) -> Self {
+        let Some((min, max)) = region.bounds() else {
+            return Self { origin: IVec3::ZERO, size: Chunk::SIZE as u32, words: vec![OCTREE_LEAF] };
+        };
+
+        let span = (max.x - min.x).max(max.y - min.y).max(max.z - min.z) as u32 + 1;
+        let size = span.next_power_of_two() * Chunk::SIZE as u32;
+        let origin = IVec3::new(min.x, min.y, min.z) * Chunk::SIZE as i32;
+
+        let mut builder = Builder { region, words: vec![0], nodes: HashMap::new() };
+        builder.words[0] = builder.build(origin, size);
+        log::debug!(
+            "baked {} chunks into a dag of {} words ({} unique nodes)",
+            region.len(),
+            builder.words.len(),
+            builder.nodes.len(),
+        );
+
+        Self { origin, size, words: builder.words }
+    }
+
+    pub fn origin(&self) -> IVec3 {
+        self.origin
+    }
+
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    pub fn words(&self) -> &[u32] {
+        &self.words
+    }
+
+    /// block at world voxel coords, air outside the dag
+    pub fn get_block(&self, x: i32, y: i32, z: i32) -> u8 {
+        let local = IVec3::new(x, y, z) - self.origin;
+        if local.cmplt(IVec3::ZERO).any() || local.cmpge(IVec3::splat(self.size as i32)).any() {
+            return 0;
+        }
+        self.lookup(local).0
+    }
+
+    /// leaf holding a dag-local voxel: its block, min corner and size
+    fn lookup(&self, v: IVec3) -> (u8, IVec3, i32) {
+        let mut word = self.words[0];
+        let mut corner = IVec3::ZERO;
+        let mut size = self.size as i32;
+
+        while word & OCTREE_LEAF == 0 {
+            size /= 2;
+            let upper = v.cmpge(corner + size);
+            corner += IVec3::select(upper, IVec3::splat(size), IVec3::ZERO);
+            let child = upper.bitmask();
+            word = self.words[(word + child) as usize];
+        }
+        (word as u8, corner, size)
+    }
+
+    /// first solid voxel along a world-space ray, `t` in units of `dir`
+    pub fn raycast(&self, origin: Vec3, dir: Vec3, max_t: f32) -> Option<OctreeHit> {
+        self.raycast_steps(origin, dir, max_t).0
+    }
+
+    /// `raycast` and the number of leaves it looked up
+    fn raycast_steps(&self, origin: Vec3, dir: Vec3, max_t: f32) -> (Option<OctreeHit>, u32) {
+        let lo = self.origin.as_vec3();
+        let hi = lo + self.size as f32;
+        let inv = dir.recip();
+        let (t0, t1) = ((lo - origin) * inv, (hi - origin) * inv);
+        let (near, far) = (t0.min(t1), t0.max(t1));
+
+        let mut t = near.max_element().max(0.0);
+        let exit = far.min_element().min(max_t);
+        let mut normal = if near.max_element() <= 0.0 { Vec3::ZERO } else { face_normal(near, dir) };
+        let mut steps = 0;
+
+        while t < exit {
+            steps += 1;
+            let p = origin + dir * (t + EPSILON) - lo;
+            let v = p.floor().as_ivec3().clamp(IVec3::ZERO, IVec3::splat(self.size as i32 - 1));
+            let (block, corner, size) = self.lookup(v);
+            if block != 0 {
+                return (Some(OctreeHit { t, block, normal }), steps);
+            }
+
+            let exits = exit_times(origin, dir, lo + corner.as_vec3(), size as f32);
+            t = exits.min_element().max(t + EPSILON);
+            // entering the next leaf through the face on whichever axis was left first
+            normal = face_normal(-exits, dir);
+        }
+
+        (None, steps)
+    }
+
+    /// header of origin and size ahead of the words, as the shader reads it
+    pub fn encode_gpu(&self) -> Vec<u32> {
+        let mut words = vec![self.origin.x as u32, self.origin.y as u32, self.origin.z as u32, self.size];
+        words.extend_from_slice(&self.words);
+        words
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut file = io::BufWriter::new(std::fs::File::create(path)?);
+        self.write_to(&mut file)?;
+        file.flush()
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::read_from(&mut io::BufReader::new(std::fs::File::open(path)?))
+    }
+
+    /// magic, version, origin, size, word count, then the words, little endian
+    pub fn write_to(&self, out: &mut impl Write) -> io::Result<()> {
+        out.write_all(MAGIC)?;
+        for v in [VERSION, self.origin.x as u32, self.origin.y as u32, self.origin.z as u32, self.size] {
+            out.write_all(&v.to_le_bytes())?;
+        }
+        out.write_all(&(self.words.len() as u32).to_le_bytes())?;
+        for word in &self.words {
+            out.write_all(&word.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    pub fn read_from(input: &mut impl Read) -> io::Result<Self> {
+        let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+
+        let mut magic = [0; 4];
+        input.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid("not a dag file".into()));
+        }
+        let version = read_u32(input)?;
+        if version != VERSION {
+            return Err(invalid(format!("unsupported dag version {version}")));
+        }
+
+        let [x, y, z] = [read_u32(input)?, read_u32(input)?, read_u32(input)?].map(|v| v as i32);
+        let size = read_u32(input)?;
+        if !size.is_power_of_two() || size < Chunk::SIZE as u32 {
+            return Err(invalid(format!("dag size {size} is not a power of two of at least a chunk")));
+        }
+
+        // no dag holds more words than the full octree it was folded from,
+        // so a larger count is corrupt. the header is still not trusted with
+        // the allocation, words are read before room is made for them
+        let count = read_u32(input)? as usize;
+        if count > max_words(size) {
+            return Err(invalid(format!("{count} words is more than a dag of size {size} can hold")));
+        }
+        let mut words = Vec::with_capacity(count.min(1 << 16));
+        for _ in 0..count {
+            words.push(read_u32(input)?);
+        }
+        if words.is_empty() {
+            return Err(invalid("dag has no root".into()));
+        }
+
+        // the dag is built bottom-up, so every node's children are stored
+        // before it and only the root's come after. anything else could
+        // point past the end or round in a cycle, where lookups never finish
+        for (index, &word) in words.iter().enumerate() {
+            if word & OCTREE_LEAF != 0 {
+                continue;
+            }
+            let (first, end) = (word as usize, word as usize + 8);
+            let ordered = if index == 0 { first > 0 && end <= count } else { end <= index };
+            if !ordered {
+                return Err(invalid(format!("dag node {index} points at children {first}..{end}")));
+            }
+        }
+
+        Ok(Self { origin: IVec3::new(x, y, z), size, words })
+    }
+}
+
+impl Builder<'_> {
+    fn build(&mut self, min: IVec3, size: u32) -> u32 {
+        if size == Chunk::SIZE as u32 {
+            let pos = ChunkPos { x: min.x / Chunk::SIZE as i32, y: min.y / Chunk::SIZE as i32, z: min.z / Chunk::SIZE as i32 };
+            return match self.region.get(pos) {
+                Some(chunk) => self.build_chunk(chunk, IVec3::ZERO, size),
+                None => OCTREE_LEAF,
+            };
+        }
+
+        let half = size / 2;
+        let children = std::array::from_fn(|i| self.build(min + child_offset(i) * half as i32, half));
+        self.intern(children)
+    }
+
+    fn build_chunk(&mut self, chunk: &Chunk, min: IVec3, size: u32) -> u32 {
+        if size == 1 {
+            return OCTREE_LEAF | chunk.get_block(min.x as usize, min.y as usize, min.z as usize) as u32;
+        }
+
+        let half = size / 2;
+        let children = std::array::from_fn(|i| self.build_chunk(chunk, min + child_offset(i) * half as i32, half));
+        self.intern(children)
+    }
+
+    /// collapse uniform children into one leaf, otherwise share an identical node if one exists
+    fn intern(&mut self, children: [u32; 8]) -> u32 {
+        if children[0] & OCTREE_LEAF != 0 && children.iter().all(|&c| c == children[0]) {
+            return children[0];
+        }
+
+        let words = &mut self.words;
+        *self.nodes.entry(children).or_insert_with(|| {
+            let index = words.len() as u32;
+            words.extend(children);
+            index
+        })
+    }
+}
+
+/// words in a full octree of `size` voxels a side, more than any dag of that
+/// size needs
+fn max_words(size: u32) -> usize {
+    let levels = size.trailing_zeros();
+    (1..=levels).fold(1usize, |words, level| words.saturating_add(8usize.saturating_pow(level)))
+}
+
+/// offset of child `i` in units of the child size, x fastest then y then z
+fn child_offset(i: usize) -> IVec3 {
+    IVec3::new((i & 1) as i32, ((i >> 1) & 1) as i32, (i >> 2) as i32)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn region() -> Region {
+        let mut region = Region::new();
+        let mut floor = Chunk::new();
+        let mut pillar = Chunk::new();
+        for z in 0..Chunk::SIZE {
+            for x in 0..Chunk::SIZE {
+                floor.set_block(x, 0, z, 1);
+            }
+        }
+        for y in 0..Chunk::SIZE {
+            pillar.set_block(3, y, 5, 2);
+        }
+        region.insert(ChunkPos { x: -1, y: 0, z: 0 }, floor.clone());
+        region.insert(ChunkPos { x: 0, y: 0, z: 0 }, floor);
+        region.insert(ChunkPos { x: 0, y: 1, z: 1 }, pillar);
+        region
+    }
+
+    #[test]
+    fn blocks_match_the_region() {
+        let region = region();
+        let dag = SparseVoxelDag::from_region(&region);
+        for (pos, chunk) in region.chunks() {
+            for z in 0..Chunk::SIZE {
+                for y in 0..Chunk::SIZE {
+                    for x in 0..Chunk::SIZE {
+                        let world = IVec3::new(pos.x, pos.y, pos.z) * Chunk::SIZE as i32 + IVec3::new(x as i32, y as i32, z as i32);
+                        assert_eq!(dag.get_block(world.x, world.y, world.z), chunk.get_block(x, y, z), "{world}");
+                    }
+                }
+            }
+        }
+        assert_eq!(dag.get_block(-1000, 0, 0), 0);
+    }
+
+    #[test]
+    fn identical_chunks_share_nodes() {
+        let mut twice = Region::new();
+        let mut once = Region::new();
+        let (_, chunk) = region().into_chunks().find(|(pos, _)| pos.y == 0).unwrap();
+        twice.insert(ChunkPos { x: 0, y: 0, z: 0 }, chunk.clone());
+        twice.insert(ChunkPos { x: 1, y: 0, z: 0 }, chunk.clone());
+        once.insert(ChunkPos { x: 0, y: 0, z: 0 }, chunk);
+        once.insert(ChunkPos { x: 1, y: 0, z: 0 }, Chunk::new());
+        assert!(SparseVoxelDag::from_region(&twice).words().len() <= SparseVoxelDag::from_region(&once).words().len());
+    }
+
+    #[test]
+    fn encoding_round_trips() {
+        let dag = SparseVoxelDag::from_region(&region());
+        let mut bytes = Vec::new();
+        dag.write_to(&mut bytes).unwrap();
+        let read = SparseVoxelDag::read_from(&mut bytes.as_slice()).unwrap();
+        assert_eq!((read.origin(), read.size(), read.words()), (dag.origin(), dag.size(), dag.words()));
+
+        bytes[0] = b'X';
+        assert!(SparseVoxelDag::read_from(&mut bytes.as_slice()).is_err());
+    }
+
+    /// a dag file with the given size and words, as `write_to` lays it out
+    fn file(size: u32, count: u32, words: &[u32]) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        for v in [VERSION, 0, 0, 0, size, count].into_iter().chain(words.iter().copied()) {
+            bytes.extend_from_slice(&v.to_le_bytes());
+        }
+        bytes
+    }
+
+    #[test]
+    fn corrupt_files_are_rejected() {
+        let leaf = OCTREE_LEAF | 1;
+        let read = |bytes: Vec<u8>| SparseVoxelDag::read_from(&mut bytes.as_slice()).map(|dag| dag.words().len());
+        let invalid = |bytes| read(bytes).map_err(|e| e.kind()) == Err(io::ErrorKind::InvalidData);
+
+        // a root and eight leaves is fine
+        assert_eq!(read(file(32, 9, &[1, leaf, leaf, leaf, leaf, leaf, leaf, leaf, leaf])).unwrap(), 9);
+        // a header asking for far more words than the size allows
+        assert!(invalid(file(32, u32::MAX, &[])));
+        // a child pointing back at the root, which would loop forever
+        assert!(invalid(file(32, 9, &[1, 0, leaf, leaf, leaf, leaf, leaf, leaf, leaf])));
+        // a child pointing at itself
+        assert!(invalid(file(32, 9, &[1, 1, leaf, leaf, leaf, leaf, leaf, leaf, leaf])));
+        // a root pointing at itself
+        assert!(invalid(file(32, 9, &[0, leaf, leaf, leaf, leaf, leaf, leaf, leaf, leaf])));
+        // and past the end
+        assert!(invalid(file(32, 9, &[2, leaf, leaf, leaf, leaf, leaf, leaf, leaf, leaf])));
+    }
+
+    #[test]
+    fn rays_stop_at_the_first_block() {
+        let dag = SparseVoxelDag::from_region(&region());
+        let hit = dag.raycast(Vec3::new(-8.5, 10.0, 4.5), Vec3::NEG_Y, 100.0).expect("ray should hit the floor");
+        assert_eq!(hit.block, 1);
+        assert!((hit.t - 9.0).abs() < 1e-3);
+        assert_eq!(hit.normal, Vec3::Y);
+
+        let hit = dag.raycast(Vec3::new(-5.0, 40.5, 37.5), Vec3::X, 100.0).expect("ray should hit the pillar");
+        assert_eq!(hit.block, 2);
+        assert!((hit.t - 8.0).abs() < 1e-3);
+        assert!(dag.raycast(Vec3::new(-5.0, 40.5, 30.5), Vec3::X, 100.0).is_none());
+    }
+
+    #[test]
+    fn axis_aligned_rays_step_by_leaf() {
+        let dag = SparseVoxelDag::from_region(&region());
+        // down the empty column above the floor: a handful of leaves per
+        // level of the tree, not one epsilon at a time
+        let (hit, steps) = dag.raycast_steps(Vec3::new(-8.5, 63.5, 4.5), Vec3::NEG_Y, 100.0);
+        let hit = hit.expect("ray should hit the floor");
+        assert_eq!(hit.normal, Vec3::Y);
+        assert!(steps <= 16, "{steps} steps");
+
+        let (hit, steps) = dag.raycast_steps(Vec3::new(-31.5, 20.5, 0.5), Vec3::X, 100.0);
+        assert!(hit.is_none());
+        assert!(steps <= 16, "{steps} steps");
+    }
+}
@@ -0,0 +1,146 @@
+use std::sync::Arc;
+use crate::world::block;
+use super::{NoiseKind, NoiseSource};
+
+/// something planted on top of the surface, a column of `height` blocks
+#[derive(Debug, Clone, PartialEq)]
+pub struct Decoration {
+    pub block: u8,
+    pub height: u32,
+    /// odds per surface column, 0 to 1
+    pub chance: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Biome {
+    pub name: String,
+    /// where the biome sits in climate space, both roughly -1 to 1
+    pub temperature: f64,
+    pub humidity: f64,
+    /// top block of every column
+    pub surface: u8,
+    /// blocks under the surface, stone below them
+    pub filler: u8,
+    pub filler_depth: u32,
+    /// added to the base ground height
+    pub height_offset: f64,
+    /// multiplies the heightmap's amplitude, below 1 for plains, above for hills
+    pub height_scale: f64,
+    pub decorations: Vec<Decoration>,
+}
+
+/// height modifiers of the biomes around a column, weighted by how close the
+/// column's climate is to each, plus whichever biome is closest
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BiomeBlend {
+    pub biome: usize,
+    pub height_offset: f64,
+    pub height_scale: f64,
+}
+
+/// picks biomes from two low frequency climate maps, temperature and
+/// humidity. a column belongs to the biome nearest its climate, but height
+/// blends across every biome nearby so borders slope instead of stepping
+#[derive(Clone)]
+pub struct BiomeMap {
+    temperature: Arc<dyn NoiseSource>,
+    humidity: Arc<dyn NoiseSource>,
+    biomes: Vec<Biome>,
+    /// voxels per unit of climate noise, bigger means wider biomes
+    scale: f64,
+    /// distance in climate space over which neighbouring biomes fade out
+    blend: f64,
+}
+
+impl BiomeMap {
+    pub fn new(seed: u32, biomes: Vec<Biome>) -> Self {
+        Self::with_climate_noise(seed, biomes, NoiseKind::Perlin)
+    }
+
+    /// climate maps sampled from `noise` instead of plain perlin. fractal
+    /// kinds get a couple of octaves, climate is meant to change slowly
+    pub fn with_climate_noise(seed: u32, biomes: Vec<Biome>, noise: NoiseKind) -> Self {
+        assert!(!biomes.is_empty(), "a biome map needs at least one biome");
+        let climate = |offset| Arc::<dyn NoiseSource>::from(noise.build_with(seed.wrapping_add(offset), 2, 2.0, 0.5));
+        Self {
+            temperature: climate(10),
+            humidity: climate(11),
+            biomes,
+            scale: 512.0,
+            blend: 0.12,
+        }
+    }
+
+    pub fn with_default_biomes(seed: u32) -> Self {
+        Self::new(seed, default_biomes())
+    }
+
+    pub fn biomes(&self) -> &[Biome] {
+        &self.biomes
+    }
+
+    /// temperature and humidity of a column
+    pub fn climate(&self, x: i32, z: i32) -> (f64, f64) {
+        let (x, z) = (x as f64 / self.scale, z as f64 / self.scale);
+        (self.temperature.sample_2d(x, z), self.humidity.sample_2d(x, z))
+    }
+
+    pub fn biome_at(&self, x: i32, z: i32) -> &Biome {
+        &self.biomes[self.blend(x, z).biome]
+    }
+
+    pub fn blend(&self, x: i32, z: i32) -> BiomeBlend {
+        let (temperature, humidity) = self.climate(x, z);
+        let distances: Vec<f64> = self.biomes.iter()
+            .map(|b| (b.temperature - temperature).powi(2) + (b.humidity - humidity).powi(2))
+            .collect();
+
+        let (nearest, &closest) = distances.iter().enumerate()
+            .min_by(|a, b| a.1.total_cmp(b.1))
+            .unwrap();
+
+        // gaussian falloff relative to the nearest biome, which keeps weight 1,
+        // so far away climates can't underflow everything to zero
+        let mut total = 0.0;
+        let mut offset = 0.0;
+        let mut scale = 0.0;
+        for (biome, d) in self.biomes.iter().zip(&distances) {
+            let weight = (-(d - closest) / (self.blend * self.blend)).exp();
+            total += weight;
+            offset += biome.height_offset * weight;
+            scale += biome.height_scale * weight;
+        }
+
+        BiomeBlend { biome: nearest, height_offset: offset / total, height_scale: scale / total }
+    }
+}
+
+pub fn default_biomes() -> Vec<Biome> {
+    let biome = |name: &str, temperature, humidity, surface, filler, height_offset, height_scale, decorations| Biome {
+        name: name.into(),
+        temperature,
+        humidity,
+        surface,
+        filler,
+        filler_depth: 3,
+        height_offset,
+        height_scale,
+        decorations,
+    };
+    let decoration = |block, height, chance| Decoration { block, height, chance };
+
+    vec![
+        biome("plains", 0.0, 0.0, block::GRASS, block::DIRT, 0.0, 0.5, vec![
+            decoration(block::TALL_GRASS, 1, 0.1),
+            decoration(block::FLOWER, 1, 0.02),
+        ]),
+        biome("forest", 0.1, 0.45, block::GRASS, block::DIRT, 2.0, 1.0, vec![
+            decoration(block::TALL_GRASS, 1, 0.15),
+        ]),
+        biome("desert", 0.5, -0.45, block::SAND, block::SAND, -2.0, 0.4, vec![
+            decoration(block::CACTUS, 3, 0.005),
+        ]),
+        biome("tundra", -0.5, 0.1, block::SNOW, block::DIRT, 0.0, 0.6, Vec::new()),
+        biome("mountains", -0.3, -0.4, block::STONE, block::STONE, 12.0, 2.5, Vec::new()),
+    ]
+}
@@ -0,0 +1,287 @@
+use std::collections::{HashMap, VecDeque};
+use std::f64::consts::{PI, TAU};
+use std::sync::Arc;
+use glam::{DVec3, IVec3};
+use noise::{NoiseFn, Perlin};
+use parking_lot::Mutex;
+use crate::world::block;
+use super::{hash, Chunk, ChunkPos, Rng};
+
+/// carver paths start per region, a square of this many voxels on x and z.
+/// a chunk replays every path from regions close enough to reach it, so a
+/// tunnel comes out the same in each chunk it passes through
+pub const CARVER_REGION: i32 = 128;
+/// regions whose paths are kept before the oldest is dropped
+const CACHE_REGIONS: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CarverParams {
+    /// winding tunnels started per region
+    pub worms_per_region: u32,
+    /// steps of one voxel each
+    pub worm_length: u32,
+    pub worm_radius: (f64, f64),
+    /// odds a region gets a ravine, 0 to 1
+    pub ravine_chance: f64,
+    pub ravine_length: u32,
+    /// half-width across the ravine
+    pub ravine_width: (f64, f64),
+    /// half-height, many times the width
+    pub ravine_depth: f64,
+    /// paths start between these heights, and nothing above `max_y` is carved
+    pub min_y: i32,
+    pub max_y: i32,
+    /// never carved, nor is anything right under it, so seas and rivers
+    /// don't drain into the caves below
+    pub fluid: u8,
+}
+
+impl Default for CarverParams {
+    fn default() -> Self {
+        Self {
+            worms_per_region: 4,
+            worm_length: 120,
+            worm_radius: (1.5, 3.5),
+            ravine_chance: 0.15,
+            ravine_length: 90,
+            ravine_width: (1.5, 3.0),
+            ravine_depth: 14.0,
+            min_y: 0,
+            max_y: 60,
+            fluid: block::WATER,
+        }
+    }
+}
+
+/// cuts perlin worm tunnels and ravines out of solid terrain. each region's
+/// paths are walked once and remembered, every chunk they reach reuses them
+pub struct CaveCarver {
+    seed: u32,
+    params: CarverParams,
+    /// steers each path. sampled along the path, so turns are smooth
+    steer: Perlin,
+    cache: Mutex<PathCache>,
+}
+
+#[derive(Default)]
+struct PathCache {
+    regions: HashMap<(i32, i32), Arc<Vec<Cut>>>,
+    order: VecDeque<(i32, i32)>,
+}
+
+/// one step of a path: where it is and how wide the cut is
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Cut {
+    centre: DVec3,
+    /// half extents of the ellipsoid carved, equal for worms, tall for ravines
+    radius: DVec3,
+}
+
+impl CaveCarver {
+    pub fn new(seed: u32, params: CarverParams) -> Self {
+        Self { seed, params, steer: Perlin::new(seed.wrapping_add(20)), cache: Mutex::new(PathCache::default()) }
+    }
+
+    pub fn seed(&self) -> u32 {
+        self.seed
+    }
+
+    pub fn params(&self) -> &CarverParams {
+        &self.params
+    }
+
+    /// carve a chunk. `fluid_above` says whether the voxel just over the
+    /// chunk's top layer, at local x and z, will hold fluid; the chunk above
+    /// may not have its surface yet, so the generator answers for it
+    pub fn carve(&self, chunk: &mut Chunk, pos: ChunkPos, fluid_above: impl Fn(usize, usize) -> bool) {
+        let p = &self.params;
+        let lo = pos.origin();
+        let hi = lo + Chunk::SIZE as i32;
+        if lo.y > p.max_y {
+            return;
+        }
+
+        // farthest a path can wander from its start, plus its widest cut
+        let reach = p.worm_length.max(p.ravine_length) as i32 + p.ravine_depth.max(p.worm_radius.1) as i32 + 1;
+        let region = |v: i32| v.div_euclid(CARVER_REGION);
+        for rz in region(lo.z - reach)..=region(hi.z + reach) {
+            for rx in region(lo.x - reach)..=region(hi.x + reach) {
+                for cut in self.paths(rx, rz).iter() {
+                    self.apply(chunk, lo, cut, &fluid_above);
+                }
+            }
+        }
+    }
+
+    /// every cut of the paths a region starts, walked on first use
+    fn paths(&self, rx: i32, rz: i32) -> Arc<Vec<Cut>> {
+        if let Some(paths) = self.cache.lock().regions.get(&(rx, rz)) {
+            return paths.clone();
+        }
+
+        // walked without the lock; a region walked twice at once comes out
+        // the same both times
+        let mut cuts = Vec::new();
+        self.for_each_cut(rx, rz, |cut| cuts.push(*cut));
+        let paths = Arc::new(cuts);
+        let mut cache = self.cache.lock();
+        if cache.regions.insert((rx, rz), paths.clone()).is_none() {
+            cache.order.push_back((rx, rz));
+            if cache.order.len() > CACHE_REGIONS {
+                let oldest = cache.order.pop_front().unwrap();
+                cache.regions.remove(&oldest);
+            }
+        }
+        paths
+    }
+
+    /// walk every path a region starts
+    fn for_each_cut(&self, rx: i32, rz: i32, mut f: impl FnMut(&Cut)) {
+        let p = &self.params;
+        let mut rng = Rng(hash(self.seed ^ 0xca5e, rx, 0, rz));
+        let start = |rng: &mut Rng| {
+            DVec3::new(
+                (rx * CARVER_REGION) as f64 + rng.unit() * CARVER_REGION as f64,
+                rng.range(p.min_y as f64, p.max_y.max(p.min_y + 1) as f64),
+                (rz * CARVER_REGION) as f64 + rng.unit() * CARVER_REGION as f64,
+            )
+        };
+
+        for worm in 0..p.worms_per_region {
+            let origin = start(&mut rng);
+            let (yaw, pitch) = (rng.unit() * TAU, rng.range(-0.4, 0.4));
+            let radius = rng.range(p.worm_radius.0, p.worm_radius.1);
+            let id = (rx as f64 * 31.7 + rz as f64 * 17.3 + worm as f64 * 5.1) % 1000.0;
+            self.walk(origin, yaw, pitch, p.worm_length, id, 1.0, |t, centre| {
+                // thickest in the middle, tapering shut at both ends
+                let r = radius * (0.3 + 0.7 * (t * PI).sin());
+                f(&Cut { centre, radius: DVec3::splat(r) });
+            });
+        }
+
+        if rng.unit() < p.ravine_chance {
+            let origin = start(&mut rng);
+            let yaw = rng.unit() * TAU;
+            let width = rng.range(p.ravine_width.0, p.ravine_width.1);
+            let id = (rx as f64 * 13.9 + rz as f64 * 29.3) % 1000.0 + 500.0;
+            // ravines barely climb or dive and turn slowly
+            self.walk(origin, yaw, 0.0, p.ravine_length, id, 0.3, |t, centre| {
+                let taper = (t * PI).sin();
+                let (w, h) = (width * taper + 0.5, p.ravine_depth * taper + 0.5);
+                f(&Cut { centre, radius: DVec3::new(w, h, w) });
+            });
+        }
+    }
+
+    /// step a path forward one voxel at a time, turning with the steering
+    /// noise. `f` gets the progress from 0 to 1 and the position
+    #[allow(clippy::too_many_arguments)]
+    fn walk(
+        &self,
+        mut at: DVec3,
+        mut yaw: f64,
+        mut pitch: f64,
+        length: u32,
+        id: f64,
+        turn: f64,
+        mut f: impl FnMut(f64, DVec3),
+    ) {
+        for step in 0..length {
+            let s = step as f64 / 16.0;
+            yaw += self.steer.get([s, id, 0.0]) * 0.25 * turn;
+            pitch = (pitch * 0.9 + self.steer.get([s, id, 10.0]) * 0.1 * turn).clamp(-0.8, 0.8);
+            at += DVec3::new(yaw.cos() * pitch.cos(), pitch.sin(), yaw.sin() * pitch.cos());
+            f(step as f64 / length.max(1) as f64, at);
+        }
+    }
+
+    /// air out the part of an ellipsoid inside the chunk whose low corner is `lo`
+    fn apply(&self, chunk: &mut Chunk, lo: IVec3, cut: &Cut, fluid_above: impl Fn(usize, usize) -> bool) {
+        let min = (cut.centre - cut.radius).floor().as_ivec3().max(lo);
+        let mut max = (cut.centre + cut.radius).ceil().as_ivec3().min(lo + (Chunk::SIZE as i32 - 1));
+        max.y = max.y.min(self.params.max_y);
+        if min.cmpgt(max).any() {
+            return;
+        }
+
+        for z in min.z..=max.z {
+            for y in min.y..=max.y {
+                for x in min.x..=max.x {
+                    let d = (DVec3::new(x as f64, y as f64, z as f64) + 0.5 - cut.centre) / cut.radius;
+                    if d.length_squared() > 1.0 {
+                        continue;
+                    }
+                    let local = (IVec3::new(x, y, z) - lo).as_uvec3();
+                    let (lx, ly, lz) = (local.x as usize, local.y as usize, local.z as usize);
+                    let current = chunk.get_block(lx, ly, lz);
+                    let under_fluid = if ly + 1 < Chunk::SIZE {
+                        chunk.get_block(lx, ly + 1, lz) == self.params.fluid
+                    } else {
+                        fluid_above(lx, lz)
+                    };
+                    if current != block::AIR && current != self.params.fluid && !under_fluid {
+                        chunk.set_block(lx, ly, lz, block::AIR);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stone() -> Chunk {
+        let mut chunk = Chunk::new();
+        for z in 0..Chunk::SIZE {
+            for y in 0..Chunk::SIZE {
+                for x in 0..Chunk::SIZE {
+                    chunk.set_block(x, y, z, block::STONE);
+                }
+            }
+        }
+        chunk
+    }
+
+    fn carved(carver: &CaveCarver, pos: ChunkPos) -> Vec<u8> {
+        let mut chunk = stone();
+        carver.carve(&mut chunk, pos, |_, _| false);
+        chunk.blocks().to_vec()
+    }
+
+    #[test]
+    fn cached_paths_carve_the_same() {
+        let pos = ChunkPos { x: 1, y: 1, z: -2 };
+        let carver = CaveCarver::new(9, CarverParams::default());
+        let cold = carved(&carver, pos);
+        assert!(!carver.cache.lock().regions.is_empty());
+        assert!(cold == carved(&carver, pos));
+        assert!(cold == carved(&CaveCarver::new(9, CarverParams::default()), pos));
+    }
+
+    #[test]
+    fn paths_are_the_same_from_any_chunk() {
+        let carver = CaveCarver::new(4, CarverParams::default());
+        let first = carver.paths(0, 0);
+        let _ = carved(&carver, ChunkPos { x: 3, y: 0, z: 3 });
+        let fresh = CaveCarver::new(4, CarverParams::default());
+        assert_eq!(*first, *fresh.paths(0, 0));
+    }
+
+    #[test]
+    fn top_row_under_fluid_is_kept() {
+        let carver = CaveCarver::new(1, CarverParams::default());
+        let top = Chunk::SIZE as f64;
+        let cut = Cut { centre: DVec3::new(16.0, top - 1.0, 16.0), radius: DVec3::splat(3.0) };
+
+        let mut chunk = stone();
+        carver.apply(&mut chunk, IVec3::ZERO, &cut, |_, _| true);
+        assert_eq!(chunk.get_block(16, Chunk::SIZE - 1, 16), block::STONE);
+        assert_eq!(chunk.get_block(16, Chunk::SIZE - 3, 16), block::AIR);
+
+        let mut chunk = stone();
+        carver.apply(&mut chunk, IVec3::ZERO, &cut, |_, _| false);
+        assert_eq!(chunk.get_block(16, Chunk::SIZE - 1, 16), block::AIR);
+    }
+}
@@ -0,0 +1,110 @@
+use std::io;
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use crate::world::block;
+use super::{ErosionParams, NoiseKind};
+
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigError {
+    #[error("couldn't access generator config: {0}")]
+    Io(#[from] io::Error),
+    #[error("invalid generator config: {0}")]
+    Parse(#[from] toml::de::Error),
+    #[error("couldn't write generator config: {0}")]
+    Serialize(#[from] toml::ser::Error),
+    #[error("the current generator doesn't take a config")]
+    Unsupported,
+}
+
+/// blocks the heightmap generator places outside of what biomes decide
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BlockConfig {
+    /// everything below the biome's filler
+    pub stone: u8,
+    /// what seas and rivers are made of
+    pub water: u8,
+    /// beaches and shallow sea floor
+    pub sand: u8,
+    /// deep sea floor
+    pub gravel: u8,
+}
+
+impl Default for BlockConfig {
+    fn default() -> Self {
+        Self { stone: block::STONE, water: block::WATER, sand: block::SAND, gravel: block::GRAVEL }
+    }
+}
+
+/// knobs of the heightmap generator, read from and written to toml. missing
+/// keys fall back to the defaults, so a file only has to list what it changes
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GeneratorConfig {
+    /// backend the heightmap samples
+    pub noise: NoiseKind,
+    /// noise layers summed into the heightmap, for the fractal kinds
+    pub octaves: u32,
+    /// frequency multiplier from one octave to the next
+    pub lacunarity: f64,
+    /// amplitude multiplier from one octave to the next
+    pub persistence: f64,
+    /// horizontal size of hills in voxels
+    pub scale: f64,
+    /// height of hills above and below `base_height`, before biomes scale it
+    pub amplitude: f64,
+    pub base_height: f64,
+    /// world y of the sea surface, air at or below it fills with water
+    pub sea_level: i32,
+    /// columns at most this far above the sea become beach
+    pub beach_height: i32,
+    /// horizontal size of the river network in voxels
+    pub river_scale: f64,
+    /// share of the river noise that counts as river, 0 turns rivers off
+    pub river_width: f64,
+    /// how far below sea level river beds sit
+    pub river_depth: f64,
+    pub blocks: BlockConfig,
+    /// erode the heightmap before it is turned into voxels, off when missing
+    pub erosion: Option<ErosionParams>,
+}
+
+impl Default for GeneratorConfig {
+    fn default() -> Self {
+        Self {
+            noise: NoiseKind::default(),
+            octaves: 4,
+            lacunarity: 2.0,
+            persistence: 0.5,
+            scale: 64.0,
+            amplitude: 16.0,
+            base_height: 64.0,
+            sea_level: 62,
+            beach_height: 2,
+            river_scale: 320.0,
+            river_width: 0.04,
+            river_depth: 3.0,
+            blocks: BlockConfig::default(),
+            erosion: None,
+        }
+    }
+}
+
+impl GeneratorConfig {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        Self::from_toml(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), ConfigError> {
+        std::fs::write(path, self.to_toml()?)?;
+        Ok(())
+    }
+
+    pub fn from_toml(text: &str) -> Result<Self, ConfigError> {
+        Ok(toml::from_str(text)?)
+    }
+
+    pub fn to_toml(&self) -> Result<String, ConfigError> {
+        Ok(toml::to_string_pretty(self)?)
+    }
+}
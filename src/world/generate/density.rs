@@ -0,0 +1,165 @@
+use noise::{Fbm, Perlin};
+use crate::world::block;
+use super::{Chunk, ChunkPos, FbmSource, NoiseKind, NoiseSource, PerlinSource, TerrainGenerator};
+
+/// voxels between density samples. the field is smooth at this scale, so
+/// sampling a coarse lattice and interpolating saves most of the noise calls
+const CELL: usize = 4;
+const CORNERS: usize = Chunk::SIZE / CELL + 1;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DensityParams {
+    /// backend the terrain density samples
+    pub noise: NoiseKind,
+    /// horizontal size of terrain features in voxels
+    pub scale: f64,
+    /// vertical stretch of the noise relative to `scale`, below 1 gives
+    /// taller cliffs and overhangs
+    pub vertical_scale: f64,
+    pub octaves: u32,
+    /// density lost per voxel above `surface_level` and gained below it.
+    /// higher squashes the terrain towards a flat plane, lower lets the noise
+    /// build floating arches
+    pub squash: f64,
+    /// height where the gradient alone is neutral, the average ground level
+    pub surface_level: f64,
+    /// tunnels open where the cave noise is within this of zero, 0 turns them off
+    pub tunnel_threshold: f64,
+    /// caverns open where the cave noise rises above this, 1 or more turns them off
+    pub cavern_threshold: f64,
+    pub cave_scale: f64,
+    /// caves fade out this many voxels below `surface_level` so they rarely
+    /// punch holes in the ground
+    pub cave_depth: f64,
+    pub block: u8,
+}
+
+impl Default for DensityParams {
+    fn default() -> Self {
+        Self {
+            noise: NoiseKind::Fbm,
+            scale: 96.0,
+            vertical_scale: 0.5,
+            octaves: 4,
+            squash: 0.04,
+            surface_level: 64.0,
+            tunnel_threshold: 0.06,
+            cavern_threshold: 0.6,
+            cave_scale: 48.0,
+            cave_depth: 8.0,
+            block: block::STONE,
+        }
+    }
+}
+
+/// terrain from a 3d density field: fbm noise plus a gradient pulling it
+/// towards `surface_level`, solid wherever the sum is positive. unlike a
+/// heightmap this can fold over itself, so overhangs, arches and caves form
+pub struct DensityGenerator {
+    params: DensityParams,
+    terrain: Box<dyn NoiseSource>,
+    tunnels: PerlinSource,
+    caverns: FbmSource,
+}
+
+impl DensityGenerator {
+    pub fn new(seed: u32, params: DensityParams) -> Self {
+        let (lacunarity, persistence) = (Fbm::<Perlin>::DEFAULT_LACUNARITY, Fbm::<Perlin>::DEFAULT_PERSISTENCE);
+        Self {
+            params,
+            terrain: params.noise.build_with(seed, params.octaves, lacunarity, persistence),
+            // offset seeds so caves don't line up with the terrain
+            tunnels: PerlinSource::new(seed.wrapping_add(1)),
+            caverns: FbmSource::with_octaves(seed.wrapping_add(2), 2, lacunarity, persistence),
+        }
+    }
+
+    pub fn params(&self) -> &DensityParams {
+        &self.params
+    }
+
+    /// density at a world position, solid above zero
+    pub fn density(&self, x: f64, y: f64, z: f64) -> f64 {
+        let p = &self.params;
+        let noise = self.terrain.sample_3d(x / p.scale, y / (p.scale * p.vertical_scale), z / p.scale);
+        let terrain = noise + (p.surface_level - y) * p.squash;
+
+        // positive outside caves, so taking the minimum carves them out
+        let (cx, cy, cz) = (x / p.cave_scale, y / p.cave_scale, z / p.cave_scale);
+        let tunnel = self.tunnels.sample_3d(cx, cy, cz).abs() - p.tunnel_threshold;
+        let cavern = p.cavern_threshold - self.caverns.sample_3d(cx, cy, cz);
+        let fade = (y - (p.surface_level - p.cave_depth)).max(0.0) * 0.1;
+
+        terrain.min(tunnel.min(cavern) + fade)
+    }
+}
+
+impl TerrainGenerator for DensityGenerator {
+    fn generate_chunk(&self, chunk: &mut Chunk, pos: ChunkPos) {
+        let base = [pos.x, pos.y, pos.z].map(|v| (v * Chunk::SIZE as i32) as f64);
+
+        let mut lattice = [0.0; CORNERS * CORNERS * CORNERS];
+        for z in 0..CORNERS {
+            for y in 0..CORNERS {
+                for x in 0..CORNERS {
+                    lattice[(z * CORNERS + y) * CORNERS + x] = self.density(
+                        base[0] + (x * CELL) as f64,
+                        base[1] + (y * CELL) as f64,
+                        base[2] + (z * CELL) as f64,
+                    );
+                }
+            }
+        }
+
+        for z in 0..Chunk::SIZE {
+            for y in 0..Chunk::SIZE {
+                for x in 0..Chunk::SIZE {
+                    if trilinear(&lattice, x, y, z) > 0.0 {
+                        chunk.set_block(x, y, z, self.params.block);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// density at a voxel, blended from the eight lattice corners around it
+fn trilinear(lattice: &[f64], x: usize, y: usize, z: usize) -> f64 {
+    let (cx, cy, cz) = (x / CELL, y / CELL, z / CELL);
+    let [fx, fy, fz] = [x, y, z].map(|v| (v % CELL) as f64 / CELL as f64);
+    let at = |dx, dy, dz| lattice[((cz + dz) * CORNERS + cy + dy) * CORNERS + cx + dx];
+    let lerp = |a: f64, b: f64, t: f64| a + (b - a) * t;
+
+    let y0 = lerp(lerp(at(0, 0, 0), at(1, 0, 0), fx), lerp(at(0, 1, 0), at(1, 1, 0), fx), fy);
+    let y1 = lerp(lerp(at(0, 0, 1), at(1, 0, 1), fx), lerp(at(0, 1, 1), at(1, 1, 1), fx), fy);
+    lerp(y0, y1, fz)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn generated(generator: &DensityGenerator, pos: ChunkPos) -> Vec<u8> {
+        let mut chunk = Chunk::new();
+        generator.generate_chunk(&mut chunk, pos);
+        chunk.blocks().to_vec()
+    }
+
+    #[test]
+    fn same_seed_same_chunks() {
+        let pos = ChunkPos { x: 1, y: 1, z: -2 };
+        let chunk = generated(&DensityGenerator::new(8, DensityParams::default()), pos);
+        assert!(chunk == generated(&DensityGenerator::new(8, DensityParams::default()), pos));
+        assert!(chunk != generated(&DensityGenerator::new(9, DensityParams::default()), pos));
+        assert!(chunk.contains(&block::STONE) && chunk.contains(&0));
+    }
+
+    #[test]
+    fn every_noise_kind_is_deterministic() {
+        let pos = ChunkPos { x: 0, y: 2, z: 0 };
+        for &noise in NoiseKind::ALL {
+            let params = DensityParams { noise, ..Default::default() };
+            assert!(generated(&DensityGenerator::new(3, params), pos) == generated(&DensityGenerator::new(3, params), pos));
+        }
+    }
+}
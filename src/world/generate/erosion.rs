@@ -0,0 +1,332 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use glam::Vec2;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use super::{hash, Rng};
+
+/// columns per side of an eroded region
+pub const EROSION_REGION: i32 = 128;
+/// columns each region's result reaches past its edges. neighbouring regions
+/// are simulated apart, so across a band this wide either side of a border
+/// the two results are cross-faded, both eroded, rather than left to step
+const OVERLAP: i32 = 16;
+/// columns per side a region's result covers, itself plus the overlap
+const WINDOW: i32 = EROSION_REGION + 2 * OVERLAP;
+/// extra columns simulated around a region's window so droplets have
+/// somewhere to come from and drain to
+const MARGIN: i32 = 16;
+/// regions kept before the oldest is dropped
+const CACHE_REGIONS: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ErosionParams {
+    /// water droplets simulated per region
+    pub droplets: u32,
+    /// steps a droplet runs before it is dropped
+    pub lifetime: u32,
+    /// how much of a droplet's direction carries over each step, 0 to 1
+    pub inertia: f32,
+    /// sediment a droplet can hold per unit of speed, water and slope
+    pub capacity: f32,
+    /// smallest capacity, so droplets still carve on flat ground
+    pub min_capacity: f32,
+    /// fraction of surplus sediment dropped per step
+    pub deposition: f32,
+    /// fraction of spare capacity dug up per step
+    pub erosion: f32,
+    /// fraction of water lost per step
+    pub evaporation: f32,
+    pub gravity: f32,
+    /// passes of thermal slumping after the droplets
+    pub thermal_iterations: u32,
+    /// steepest height step between columns that thermal erosion leaves alone
+    pub talus: f32,
+    /// fraction of the excess over `talus` moved downhill per pass
+    pub thermal_rate: f32,
+}
+
+impl Default for ErosionParams {
+    fn default() -> Self {
+        Self {
+            droplets: 24_000,
+            lifetime: 40,
+            inertia: 0.1,
+            capacity: 4.0,
+            min_capacity: 0.01,
+            deposition: 0.3,
+            erosion: 0.3,
+            evaporation: 0.02,
+            gravity: 4.0,
+            thermal_iterations: 20,
+            talus: 1.2,
+            thermal_rate: 0.25,
+        }
+    }
+}
+
+/// erodes the heightmap one region at a time and remembers the result, so
+/// the simulation runs once per region rather than once per chunk. the
+/// outcome depends only on the seed, the region and the raw heights
+pub struct Eroder {
+    seed: u32,
+    params: ErosionParams,
+    cache: Mutex<RegionCache>,
+}
+
+#[derive(Default)]
+struct RegionCache {
+    regions: HashMap<(i32, i32), Arc<Vec<f32>>>,
+    order: VecDeque<(i32, i32)>,
+}
+
+/// a square grid of heights, row by row along z
+struct Grid {
+    size: usize,
+    heights: Vec<f32>,
+}
+
+impl Eroder {
+    pub fn new(seed: u32, params: ErosionParams) -> Self {
+        Self { seed, params, cache: Mutex::new(RegionCache::default()) }
+    }
+
+    pub fn params(&self) -> &ErosionParams {
+        &self.params
+    }
+
+    /// eroded height of a column. `raw` gives heights before erosion and is
+    /// only called when a region the column needs isn't cached yet
+    pub fn height(&self, x: i32, z: i32, raw: impl Fn(i32, i32) -> f64) -> f64 {
+        self.heights(x, z, 1, raw)[0]
+    }
+
+    /// eroded heights of a `size` square of columns from `x0, z0`, x fastest.
+    /// looks each region up once rather than per column
+    pub fn heights(&self, x0: i32, z0: i32, size: i32, raw: impl Fn(i32, i32) -> f64) -> Vec<f64> {
+        let mut windows: HashMap<(i32, i32), Arc<Vec<f32>>> = HashMap::new();
+        let mut out = Vec::with_capacity((size * size) as usize);
+        for z in z0..z0 + size {
+            for x in x0..x0 + size {
+                let mut height = 0.0;
+                for (rz, wz) in blend(z) {
+                    for (rx, wx) in blend(x) {
+                        if wx * wz == 0.0 {
+                            continue;
+                        }
+                        let window = windows.entry((rx, rz)).or_insert_with(|| self.region(rx, rz, &raw));
+                        let (lx, lz) = (x - rx * EROSION_REGION + OVERLAP, z - rz * EROSION_REGION + OVERLAP);
+                        height += window[(lz * WINDOW + lx) as usize] as f64 * wx * wz;
+                    }
+                }
+                out.push(height);
+            }
+        }
+        out
+    }
+
+    /// eroded heights of a region and the overlap around it, `WINDOW`
+    /// columns a row starting `OVERLAP` before the region's low corner
+    fn region(&self, rx: i32, rz: i32, raw: impl Fn(i32, i32) -> f64) -> Arc<Vec<f32>> {
+        if let Some(region) = self.cache.lock().regions.get(&(rx, rz)) {
+            return region.clone();
+        }
+
+        // simulated without the lock; a region built twice at once comes out
+        // the same both times
+        let region = Arc::new(self.erode(rx, rz, raw));
+        let mut cache = self.cache.lock();
+        if cache.regions.insert((rx, rz), region.clone()).is_none() {
+            cache.order.push_back((rx, rz));
+            if cache.order.len() > CACHE_REGIONS {
+                let oldest = cache.order.pop_front().unwrap();
+                cache.regions.remove(&oldest);
+            }
+        }
+        region
+    }
+
+    fn erode(&self, rx: i32, rz: i32, raw: impl Fn(i32, i32) -> f64) -> Vec<f32> {
+        let size = (WINDOW + 2 * MARGIN) as usize;
+        let (x0, z0) = (rx * EROSION_REGION - OVERLAP - MARGIN, rz * EROSION_REGION - OVERLAP - MARGIN);
+        let mut grid = Grid { size, heights: Vec::with_capacity(size * size) };
+        for z in 0..size as i32 {
+            for x in 0..size as i32 {
+                grid.heights.push(raw(x0 + x, z0 + z) as f32);
+            }
+        }
+
+        let mut rng = Rng(hash(self.seed ^ 0xe205, rx, 0, rz));
+        for _ in 0..self.params.droplets {
+            let start = Vec2::new(rng.unit() as f32, rng.unit() as f32) * (size - 1) as f32;
+            self.droplet(&mut grid, start, &mut rng);
+        }
+        for _ in 0..self.params.thermal_iterations {
+            self.slump(&mut grid);
+        }
+
+        // keep the window, the margin only gave droplets room
+        let mut out = Vec::with_capacity((WINDOW * WINDOW) as usize);
+        for z in MARGIN..MARGIN + WINDOW {
+            let row = z as usize * size + MARGIN as usize;
+            out.extend_from_slice(&grid.heights[row..row + WINDOW as usize]);
+        }
+        out
+    }
+
+    /// one droplet running downhill, digging where it speeds up and
+    /// dropping sediment where it slows or fills a pit
+    fn droplet(&self, grid: &mut Grid, mut pos: Vec2, rng: &mut Rng) {
+        let p = &self.params;
+        let mut dir = Vec2::ZERO;
+        let (mut speed, mut water, mut sediment) = (1.0f32, 1.0f32, 0.0f32);
+
+        for _ in 0..p.lifetime {
+            let (height, gradient) = grid.sample(pos);
+            dir = dir * p.inertia - gradient * (1.0 - p.inertia);
+            if dir.length_squared() < 1e-12 {
+                let angle = rng.unit() as f32 * std::f32::consts::TAU;
+                dir = Vec2::new(angle.cos(), angle.sin());
+            }
+            dir = dir.normalize();
+
+            let old = pos;
+            pos += dir;
+            if pos.cmplt(Vec2::ZERO).any() || pos.cmpge(Vec2::splat((grid.size - 1) as f32)).any() {
+                break;
+            }
+
+            let dh = grid.sample(pos).0 - height;
+            let capacity = (-dh * speed * water * p.capacity).max(p.min_capacity);
+            if sediment > capacity || dh > 0.0 {
+                // uphill, fill the pit behind it at most; otherwise shed the surplus
+                let deposit = if dh > 0.0 { dh.min(sediment) } else { (sediment - capacity) * p.deposition };
+                sediment -= deposit;
+                grid.add(old, deposit);
+            } else {
+                // never dig deeper than the drop, or the droplet cuts a pit under itself
+                let dig = ((capacity - sediment) * p.erosion).min(-dh);
+                sediment += dig;
+                grid.add(old, -dig);
+            }
+
+            speed = (speed * speed - dh * p.gravity).max(0.0).sqrt();
+            water *= 1.0 - p.evaporation;
+        }
+    }
+
+    /// move material off slopes steeper than the talus angle
+    fn slump(&self, grid: &mut Grid) {
+        let size = grid.size;
+        let mut delta = vec![0.0f32; size * size];
+        for z in 0..size {
+            for x in 0..size {
+                let i = z * size + x;
+                for (nx, nz) in [(x + 1, z), (x, z + 1)] {
+                    if nx >= size || nz >= size {
+                        continue;
+                    }
+                    let n = nz * size + nx;
+                    let diff = grid.heights[i] - grid.heights[n];
+                    if diff.abs() > self.params.talus {
+                        let moved = (diff.abs() - self.params.talus) * self.params.thermal_rate * 0.5 * diff.signum();
+                        delta[i] -= moved;
+                        delta[n] += moved;
+                    }
+                }
+            }
+        }
+        for (height, d) in grid.heights.iter_mut().zip(delta) {
+            *height += d;
+        }
+    }
+}
+
+/// regions whose results cover a coordinate along one axis, with weights
+/// summing to 1. a region's own columns take all of its result except within
+/// `OVERLAP` of a border, where it fades into the neighbour's
+fn blend(v: i32) -> [(i32, f64); 2] {
+    let (region, local) = (v.div_euclid(EROSION_REGION), v.rem_euclid(EROSION_REGION));
+    let fade = |offset: i32| {
+        let t = (offset as f64 + 0.5) / (2 * OVERLAP) as f64;
+        t * t * (3.0 - 2.0 * t)
+    };
+    if local < OVERLAP {
+        let w = fade(local + OVERLAP);
+        [(region, w), (region - 1, 1.0 - w)]
+    } else if local >= EROSION_REGION - OVERLAP {
+        let w = fade(local - (EROSION_REGION - OVERLAP));
+        [(region + 1, w), (region, 1.0 - w)]
+    } else {
+        [(region, 1.0), (region, 0.0)]
+    }
+}
+
+impl Grid {
+    /// bilinear height and gradient at a point inside the grid
+    fn sample(&self, pos: Vec2) -> (f32, Vec2) {
+        let (x, z) = (pos.x as usize, pos.y as usize);
+        let (u, v) = (pos.x - x as f32, pos.y - z as f32);
+        let i = z * self.size + x;
+        let (h00, h10) = (self.heights[i], self.heights[i + 1]);
+        let (h01, h11) = (self.heights[i + self.size], self.heights[i + self.size + 1]);
+
+        let gradient = Vec2::new(
+            (h10 - h00) * (1.0 - v) + (h11 - h01) * v,
+            (h01 - h00) * (1.0 - u) + (h11 - h10) * u,
+        );
+        let height = h00 * (1.0 - u) * (1.0 - v) + h10 * u * (1.0 - v) + h01 * (1.0 - u) * v + h11 * u * v;
+        (height, gradient)
+    }
+
+    /// spread `amount` over the four columns around a point
+    fn add(&mut self, pos: Vec2, amount: f32) {
+        let (x, z) = (pos.x as usize, pos.y as usize);
+        let (u, v) = (pos.x - x as f32, pos.y - z as f32);
+        let i = z * self.size + x;
+        self.heights[i] += amount * (1.0 - u) * (1.0 - v);
+        self.heights[i + 1] += amount * u * (1.0 - v);
+        self.heights[i + self.size] += amount * (1.0 - u) * v;
+        self.heights[i + self.size + 1] += amount * u * v;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn raw(x: i32, z: i32) -> f64 {
+        40.0 + (x as f64 * 0.07).sin() * 12.0 + (z as f64 * 0.05).cos() * 9.0
+    }
+
+    fn eroder(seed: u32) -> Eroder {
+        Eroder::new(seed, ErosionParams { droplets: 2_000, thermal_iterations: 4, ..Default::default() })
+    }
+
+    #[test]
+    fn cached_regions_erode_the_same() {
+        let eroder = eroder(7);
+        let cold = eroder.heights(-40, 100, 64, raw);
+        assert!(!eroder.cache.lock().regions.is_empty());
+        assert_eq!(cold, eroder.heights(-40, 100, 64, raw));
+        assert_eq!(cold, self::eroder(7).heights(-40, 100, 64, raw));
+    }
+
+    #[test]
+    fn single_columns_match_blocks_of_them() {
+        let block = eroder(3).heights(EROSION_REGION - 20, 0, 40, raw);
+        for (x, z) in [(0, 0), (19, 5), (20, 39), (39, 17)] {
+            let height = eroder(3).height(EROSION_REGION - 20 + x, z, raw);
+            assert_eq!(block[(z * 40 + x) as usize], height);
+        }
+    }
+
+    #[test]
+    fn blend_weights_sum_to_one() {
+        for v in -2 * EROSION_REGION..2 * EROSION_REGION {
+            let weights = blend(v);
+            assert!((weights[0].1 + weights[1].1 - 1.0).abs() < 1e-9, "{v}");
+        }
+    }
+}
@@ -0,0 +1,262 @@
+use glam::IVec3;
+use crate::world::block;
+use super::{hash, Chunk, ChunkPos, Rng};
+
+/// something built on top of generated terrain. shapes may reach a few
+/// voxels past the chunk that places them
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Feature {
+    /// trunk of `height` logs under a ball of leaves
+    Tree { height: u32, crown: u32 },
+    /// lump of stone half sunk into the ground
+    Boulder { radius: u32 },
+    /// blob of ore grown by a random walk through stone
+    OreVein { block: u8, size: u32 },
+}
+
+/// where and how often a feature is tried
+#[derive(Debug, Clone, PartialEq)]
+pub struct FeatureRule {
+    pub feature: Feature,
+    /// tries per chunk
+    pub attempts: u32,
+    /// odds each try goes ahead, 0 to 1
+    pub chance: f64,
+    /// biome names the feature grows in, empty for anywhere
+    pub biomes: Vec<String>,
+    /// highest world y a feature may start at
+    pub max_y: i32,
+}
+
+/// what a write may overwrite
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Replace {
+    /// only blocks of lower rank, see `rank`
+    Weaker,
+    /// only this block
+    Only(u8),
+}
+
+/// one block a feature wants placed, in world coords
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FeatureWrite {
+    pub pos: IVec3,
+    pub block: u8,
+    pub replace: Replace,
+}
+
+impl FeatureWrite {
+    /// apply to a chunk, whose low corner is at `base`. overlapping writes
+    /// don't commute, a later `Only` write can replace an earlier one, so
+    /// `World` applies them in a fixed order
+    pub fn apply(&self, chunk: &mut Chunk, base: IVec3) {
+        let local = (self.pos - base).as_uvec3();
+        let (x, y, z) = (local.x as usize, local.y as usize, local.z as usize);
+        let current = chunk.get_block(x, y, z);
+        let allowed = match self.replace {
+            Replace::Weaker => rank(self.block) > rank(current),
+            Replace::Only(block) => current == block,
+        };
+        if allowed {
+            chunk.set_block(x, y, z, self.block);
+        }
+    }
+}
+
+/// how firmly a block holds against feature writes. terrain always wins
+fn rank(block: u8) -> u8 {
+    match block {
+        block::AIR => 0,
+        block::TALL_GRASS | block::FLOWER => 1,
+        block::LEAVES => 2,
+        block::LOG | block::CACTUS => 3,
+        _ => 4,
+    }
+}
+
+/// decides features per chunk from the seed and the chunk's own terrain, so
+/// a chunk places the same features whenever and wherever it is generated
+#[derive(Debug, Clone)]
+pub struct FeaturePlacer {
+    seed: u32,
+    rules: Vec<FeatureRule>,
+}
+
+impl FeaturePlacer {
+    pub fn new(seed: u32, rules: Vec<FeatureRule>) -> Self {
+        Self { seed, rules }
+    }
+
+    pub fn with_default_rules(seed: u32) -> Self {
+        Self::new(seed, default_rules())
+    }
+
+    pub fn rules(&self) -> &[FeatureRule] {
+        &self.rules
+    }
+
+    /// every write the features starting in this chunk make, some of which
+    /// may land in neighbours. `biome` names the biome of a world column
+    pub fn place<'a>(
+        &self,
+        chunk: &Chunk,
+        pos: ChunkPos,
+        biome: impl Fn(i32, i32) -> Option<&'a str>,
+    ) -> Vec<FeatureWrite> {
+        let base = IVec3::new(pos.x, pos.y, pos.z) * Chunk::SIZE as i32;
+        let mut writes = Vec::new();
+
+        for (index, rule) in self.rules.iter().enumerate() {
+            let mut rng = Rng(hash(self.seed ^ (index as u32).wrapping_mul(0x9e37_79b9), pos.x, pos.y, pos.z));
+            for _ in 0..rule.attempts {
+                // draw everything up front so a skipped try doesn't shift the rest
+                let go = rng.unit() < rule.chance;
+                let local = IVec3::from_array([(); 3].map(|_| rng.below(Chunk::SIZE as u32) as i32));
+                let shape = rng.next();
+                if !go {
+                    continue;
+                }
+
+                let start = match rule.feature {
+                    Feature::OreVein { .. } => Some(local),
+                    _ => ground(chunk, local.x as usize, local.z as usize).map(|y| IVec3::new(local.x, y as i32, local.z)),
+                };
+                let Some(start) = start else { continue };
+                let world = base + start;
+                if world.y > rule.max_y {
+                    continue;
+                }
+                if !rule.biomes.is_empty() {
+                    match biome(world.x, world.z) {
+                        Some(name) if rule.biomes.iter().any(|b| b == name) => {}
+                        _ => continue,
+                    }
+                }
+
+                let ground_block = chunk.get_block(start.x as usize, start.y as usize, start.z as usize);
+                rule.feature.build(world, ground_block, Rng(shape), &mut writes);
+            }
+        }
+        writes
+    }
+}
+
+impl Feature {
+    /// writes for one instance standing on `at`, the ground voxel, or
+    /// centred on it for ore
+    fn build(&self, at: IVec3, ground: u8, mut rng: Rng, writes: &mut Vec<FeatureWrite>) {
+        let mut put = |pos: IVec3, block: u8, replace: Replace| writes.push(FeatureWrite { pos, block, replace });
+
+        match *self {
+            Feature::Tree { height, crown } => {
+                if !matches!(ground, block::GRASS | block::DIRT) {
+                    return;
+                }
+                let height = height as i32 + rng.below(3) as i32 - 1;
+                let top = at + IVec3::Y * height;
+                let r = crown as i32;
+                for dz in -r..=r {
+                    for dy in -r..=r {
+                        for dx in -r..=r {
+                            let d = IVec3::new(dx, dy, dz);
+                            if d.length_squared() <= r * r + 1 {
+                                put(top + d, block::LEAVES, Replace::Weaker);
+                            }
+                        }
+                    }
+                }
+                for y in 1..=height {
+                    put(at + IVec3::Y * y, block::LOG, Replace::Weaker);
+                }
+            }
+            Feature::Boulder { radius } => {
+                let r = radius as i32 + rng.below(2) as i32;
+                for dz in -r..=r {
+                    for dy in -r..=r {
+                        for dx in -r..=r {
+                            let d = IVec3::new(dx, dy, dz);
+                            if d.length_squared() <= r * r {
+                                put(at + d, block::STONE, Replace::Weaker);
+                            }
+                        }
+                    }
+                }
+            }
+            Feature::OreVein { block, size } => {
+                let mut p = at;
+                for _ in 0..size {
+                    put(p, block, Replace::Only(block::STONE));
+                    p[rng.below(3) as usize] += if rng.below(2) == 0 { -1 } else { 1 };
+                }
+            }
+        }
+    }
+}
+
+/// top terrain voxel of a column with open space above it inside the chunk.
+/// water ranks as terrain so features don't cut into seas, but nothing
+/// stands on it
+fn ground(chunk: &Chunk, x: usize, z: usize) -> Option<usize> {
+    (0..Chunk::SIZE - 1).rev().find(|&y| {
+        let block = chunk.get_block(x, y, z);
+        rank(block) == 4 && block != block::WATER && rank(chunk.get_block(x, y + 1, z)) <= 1
+    })
+}
+
+pub fn default_rules() -> Vec<FeatureRule> {
+    let rule = |feature, attempts, chance, biomes: &[&str], max_y| FeatureRule {
+        feature,
+        attempts,
+        chance,
+        biomes: biomes.iter().map(|b| b.to_string()).collect(),
+        max_y,
+    };
+
+    vec![
+        rule(Feature::Tree { height: 5, crown: 2 }, 6, 0.8, &["forest"], i32::MAX),
+        rule(Feature::Tree { height: 4, crown: 2 }, 1, 0.3, &["plains"], i32::MAX),
+        rule(Feature::Boulder { radius: 1 }, 1, 0.4, &["mountains", "tundra"], i32::MAX),
+        rule(Feature::OreVein { block: block::COAL_ORE, size: 10 }, 8, 1.0, &[], 80),
+        rule(Feature::OreVein { block: block::IRON_ORE, size: 6 }, 4, 1.0, &[], 48),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// stone up to y = 10 and, when `flooded`, water over it up to y = 20
+    fn column_chunk(flooded: bool) -> Chunk {
+        let mut chunk = Chunk::new();
+        for z in 0..Chunk::SIZE {
+            for x in 0..Chunk::SIZE {
+                for y in 0..=10 {
+                    chunk.set_block(x, y, z, block::STONE);
+                }
+                if flooded {
+                    for y in 11..=20 {
+                        chunk.set_block(x, y, z, block::WATER);
+                    }
+                }
+            }
+        }
+        chunk
+    }
+
+    fn boulders() -> FeaturePlacer {
+        FeaturePlacer::new(5, vec![FeatureRule {
+            feature: Feature::Boulder { radius: 1 },
+            attempts: 16,
+            chance: 1.0,
+            biomes: Vec::new(),
+            max_y: i32::MAX,
+        }])
+    }
+
+    #[test]
+    fn nothing_stands_on_water() {
+        let pos = ChunkPos { x: 0, y: 0, z: 0 };
+        assert!(!boulders().place(&column_chunk(false), pos, |_, _| None).is_empty());
+        assert!(boulders().place(&column_chunk(true), pos, |_, _| None).is_empty());
+    }
+}
@@ -0,0 +1,296 @@
+use noise::{NoiseFn, Perlin};
+use super::{
+    hash_unit, Biome, BiomeMap, CarverParams, CaveCarver, Chunk, ChunkPos, Eroder, FeaturePlacer, FeatureWrite,
+    GeneratorConfig, NoiseSource, TerrainGenerator,
+};
+
+/// rolling hills from a 2d noise heightmap, the default for new worlds.
+/// biomes pick each column's blocks and stretch or lift its height, rivers
+/// cut valleys down to the sea and water fills everything under sea level
+pub struct WorldGenerator {
+    noise: Box<dyn NoiseSource>,
+    /// rivers run along this noise's zero crossings
+    rivers: Perlin,
+    biomes: BiomeMap,
+    features: FeaturePlacer,
+    /// `None` for solid ground all the way down
+    carver: Option<CaveCarver>,
+    /// set when the config asks for erosion
+    eroder: Option<Eroder>,
+    config: GeneratorConfig,
+    seed: u32,
+}
+
+impl WorldGenerator {
+    pub fn new(seed: u32) -> Self {
+        Self::with_config(seed, GeneratorConfig::default())
+    }
+
+    pub fn with_config(seed: u32, config: GeneratorConfig) -> Self {
+        Self::with_biomes(seed, config, BiomeMap::with_default_biomes(seed))
+    }
+
+    pub fn with_biomes(seed: u32, config: GeneratorConfig, biomes: BiomeMap) -> Self {
+        Self {
+            noise: config.noise.build(seed, &config),
+            rivers: Perlin::new(seed.wrapping_add(30)),
+            biomes,
+            features: FeaturePlacer::with_default_rules(seed),
+            carver: Some(CaveCarver::new(seed, CarverParams { fluid: config.blocks.water, ..CarverParams::default() })),
+            eroder: config.erosion.map(|params| Eroder::new(seed, params)),
+            config,
+            seed,
+        }
+    }
+
+    pub fn seed(&self) -> u32 {
+        self.seed
+    }
+
+    pub fn biomes(&self) -> &BiomeMap {
+        &self.biomes
+    }
+
+    pub fn features(&self) -> &FeaturePlacer {
+        &self.features
+    }
+
+    pub fn set_features(&mut self, features: FeaturePlacer) {
+        self.features = features;
+    }
+
+    pub fn carver(&self) -> Option<&CaveCarver> {
+        self.carver.as_ref()
+    }
+
+    pub fn set_carver(&mut self, carver: Option<CaveCarver>) {
+        self.carver = carver;
+    }
+
+    /// world y of the top solid block of a column
+    pub fn surface_height(&self, x: i32, z: i32) -> i32 {
+        let height = match &self.eroder {
+            Some(eroder) => eroder.height(x, z, |x, z| self.raw_height(x, z)),
+            None => self.raw_height(x, z),
+        };
+        self.cut_river(x, z, height).floor() as i32
+    }
+
+    /// surface heights of every column of a chunk, x fastest. looks the
+    /// eroded regions up once rather than per column
+    pub fn column_heights(&self, pos: ChunkPos) -> Vec<i32> {
+        let (x0, z0) = (pos.x * Chunk::SIZE as i32, pos.z * Chunk::SIZE as i32);
+        let columns = (0..Chunk::SIZE as i32).flat_map(|z| (0..Chunk::SIZE as i32).map(move |x| (x0 + x, z0 + z)));
+        let Some(eroder) = &self.eroder else {
+            return columns.map(|(x, z)| self.cut_river(x, z, self.raw_height(x, z)).floor() as i32).collect();
+        };
+
+        let heights = eroder.heights(x0, z0, Chunk::SIZE as i32, |x, z| self.raw_height(x, z));
+        columns
+            .zip(heights)
+            .map(|((x, z), height)| self.cut_river(x, z, height).floor() as i32)
+            .collect()
+    }
+
+    /// lower a column into a river valley if it is near a river. valleys
+    /// slope down to a bed under sea level, so rivers fill with water the
+    /// same way seas do. carved after erosion, which would silt them up
+    fn cut_river(&self, x: i32, z: i32, height: f64) -> f64 {
+        let c = &self.config;
+        if c.river_width <= 0.0 {
+            return height;
+        }
+        let river = self.rivers.get([x as f64 / c.river_scale, z as f64 / c.river_scale]).abs();
+        if river >= c.river_width {
+            return height;
+        }
+
+        let bed = c.sea_level as f64 - c.river_depth;
+        let t = river / c.river_width;
+        let bank = t * t * (3.0 - 2.0 * t);
+        height.min(bed + (height - bed) * bank)
+    }
+
+    /// surface and filler blocks of a column: the biome's on dry land, sand
+    /// on beaches and shallow sea floor, gravel deeper down
+    fn ground_blocks(&self, biome: &Biome, height: i32) -> (u8, u8) {
+        let c = &self.config;
+        if height < c.sea_level - 6 {
+            (c.blocks.gravel, c.blocks.gravel)
+        } else if height <= c.sea_level + c.beach_height {
+            (c.blocks.sand, c.blocks.sand)
+        } else {
+            (biome.surface, biome.filler)
+        }
+    }
+
+    /// height of a column straight from the noise and biomes, before erosion
+    fn raw_height(&self, x: i32, z: i32) -> f64 {
+        let c = &self.config;
+        let blend = self.biomes.blend(x, z);
+        let noise = self.noise.sample_2d(x as f64 / c.scale, z as f64 / c.scale);
+        noise * c.amplitude * blend.height_scale + c.base_height + blend.height_offset
+    }
+
+    /// decoration standing on a column, if its roll lands on one
+    fn decoration(&self, biome: &Biome, x: i32, z: i32) -> Option<(u8, i32)> {
+        let mut roll = hash_unit(self.seed, x, 0, z);
+        for decoration in &biome.decorations {
+            if roll < decoration.chance {
+                return Some((decoration.block, decoration.height as i32));
+            }
+            roll -= decoration.chance;
+        }
+        None
+    }
+}
+
+impl TerrainGenerator for WorldGenerator {
+    fn generate_chunk(&self, chunk: &mut Chunk, pos: ChunkPos) {
+        let heights = self.column_heights(pos);
+        for x in 0..Chunk::SIZE {
+            for z in 0..Chunk::SIZE {
+                let height = heights[z * Chunk::SIZE + x];
+
+                for y in 0..Chunk::SIZE {
+                    let world_y = pos.y * Chunk::SIZE as i32 + y as i32;
+                    if world_y <= height {
+                        chunk.set_block(x, y, z, self.config.blocks.stone);
+                    }
+                }
+            }
+        }
+    }
+
+    fn build_surface(&self, chunk: &mut Chunk, pos: ChunkPos) {
+        let heights = self.column_heights(pos);
+        for x in 0..Chunk::SIZE {
+            for z in 0..Chunk::SIZE {
+                let world_x = pos.x * Chunk::SIZE as i32 + x as i32;
+                let world_z = pos.z * Chunk::SIZE as i32 + z as i32;
+
+                let height = heights[z * Chunk::SIZE + x];
+                let biome = self.biomes.biome_at(world_x, world_z);
+                let (surface, filler) = self.ground_blocks(biome, height);
+                // nothing grows on beaches or under water
+                let decoration = (height > self.config.sea_level + self.config.beach_height)
+                    .then(|| self.decoration(biome, world_x, world_z))
+                    .flatten();
+
+                for y in 0..Chunk::SIZE {
+                    let world_y = pos.y * Chunk::SIZE as i32 + y as i32;
+                    let block = match height - world_y {
+                        0 => surface,
+                        d if d > 0 && d <= biome.filler_depth as i32 => filler,
+                        d if d > 0 => continue,
+                        _ if world_y <= self.config.sea_level => self.config.blocks.water,
+                        d => match decoration {
+                            Some((block, tall)) if -d <= tall => block,
+                            _ => continue,
+                        },
+                    };
+                    chunk.set_block(x, y, z, block);
+                }
+            }
+        }
+    }
+
+    fn carve(&self, chunk: &mut Chunk, pos: ChunkPos) {
+        let Some(carver) = &self.carver else { return };
+        if pos.origin().y > carver.params().max_y {
+            return;
+        }
+        // the voxel over the top row fills with water wherever it is above
+        // the ground and at or under sea level
+        let above = pos.origin().y + Chunk::SIZE as i32;
+        if above > self.config.sea_level {
+            carver.carve(chunk, pos, |_, _| false);
+        } else {
+            let heights = self.column_heights(pos);
+            carver.carve(chunk, pos, |x, z| above > heights[z * Chunk::SIZE + x]);
+        }
+    }
+
+    fn biome_at(&self, x: i32, z: i32) -> Option<&Biome> {
+        Some(self.biomes.biome_at(x, z))
+    }
+
+    fn config(&self) -> Option<&GeneratorConfig> {
+        Some(&self.config)
+    }
+
+    fn reconfigure(&self, config: GeneratorConfig) -> Option<Box<dyn TerrainGenerator>> {
+        let mut generator = Self::with_biomes(self.seed, config, self.biomes.clone());
+        generator.features = self.features.clone();
+        generator.carver = self.carver.as_ref().map(|carver| {
+            let params = CarverParams { fluid: generator.config.blocks.water, ..*carver.params() };
+            CaveCarver::new(carver.seed(), params)
+        });
+        Some(Box::new(generator))
+    }
+
+    fn place_features(&self, chunk: &Chunk, pos: ChunkPos) -> Vec<FeatureWrite> {
+        self.features.place(chunk, pos, |x, z| Some(self.biomes.biome_at(x, z).name.as_str()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::generate::ErosionParams;
+
+    /// a chunk run through every stage, and the feature writes it makes
+    fn generated(generator: &WorldGenerator, pos: ChunkPos) -> (Vec<u8>, Vec<FeatureWrite>) {
+        let mut chunk = Chunk::new();
+        generator.generate_chunk(&mut chunk, pos);
+        generator.build_surface(&mut chunk, pos);
+        generator.carve(&mut chunk, pos);
+        let writes = generator.place_features(&chunk, pos);
+        (chunk.blocks().to_vec(), writes)
+    }
+
+    #[test]
+    fn same_seed_same_chunks() {
+        for pos in [ChunkPos { x: 0, y: 1, z: 0 }, ChunkPos { x: -3, y: 2, z: 5 }] {
+            assert!(generated(&WorldGenerator::new(42), pos) == generated(&WorldGenerator::new(42), pos));
+        }
+        let pos = ChunkPos { x: 0, y: 1, z: 0 };
+        assert!(generated(&WorldGenerator::new(42), pos).0 != generated(&WorldGenerator::new(43), pos).0);
+    }
+
+    #[test]
+    fn column_heights_match_single_columns() {
+        let erosion = ErosionParams { droplets: 2_000, thermal_iterations: 4, ..Default::default() };
+        let generator = WorldGenerator::with_config(5, GeneratorConfig { erosion: Some(erosion), ..Default::default() });
+        let pos = ChunkPos { x: 3, y: 0, z: -1 };
+        let heights = generator.column_heights(pos);
+        for (i, height) in heights.iter().enumerate() {
+            let (x, z) = (pos.x * Chunk::SIZE as i32 + (i % Chunk::SIZE) as i32, pos.z * Chunk::SIZE as i32 + (i / Chunk::SIZE) as i32);
+            assert_eq!(*height, generator.surface_height(x, z));
+        }
+    }
+
+    #[test]
+    fn air_below_sea_level_is_flooded() {
+        let generator = WorldGenerator::new(7);
+        let (sea, water) = (generator.config.sea_level, generator.config.blocks.water);
+        let mut flooded = 0;
+        for (x, z) in (-8..8).flat_map(|x| (-8..8).map(move |z| (x, z))) {
+            let pos = ChunkPos { x, y: sea.div_euclid(Chunk::SIZE as i32), z };
+            let mut chunk = Chunk::new();
+            generator.generate_chunk(&mut chunk, pos);
+            generator.build_surface(&mut chunk, pos);
+            for (i, height) in generator.column_heights(pos).into_iter().enumerate() {
+                let (cx, cz) = (i % Chunk::SIZE, i / Chunk::SIZE);
+                for y in 0..Chunk::SIZE {
+                    let world_y = pos.origin().y + y as i32;
+                    if world_y > height {
+                        assert_eq!(chunk.get_block(cx, y, cz) == water, world_y <= sea);
+                    }
+                }
+                flooded += (height < sea) as usize;
+            }
+        }
+        assert!(flooded > 0, "no column reached below sea level");
+    }
+}
@@ -0,0 +1,189 @@
+mod biome;
+mod carvers;
+mod config;
+mod density;
+mod erosion;
+mod features;
+mod heightmap;
+mod noise_source;
+mod status;
+
+pub use biome::{default_biomes, Biome, BiomeBlend, BiomeMap, Decoration};
+pub use carvers::{CarverParams, CaveCarver, CARVER_REGION};
+pub use config::{BlockConfig, ConfigError, GeneratorConfig};
+pub use density::{DensityGenerator, DensityParams};
+pub use erosion::{Eroder, ErosionParams, EROSION_REGION};
+pub use features::{default_rules, Feature, FeaturePlacer, FeatureRule, FeatureWrite, Replace};
+pub use heightmap::WorldGenerator;
+#[cfg(feature = "advanced-noise")]
+pub use noise_source::FastNoiseSource;
+pub use noise_source::{FbmSource, NoiseKind, NoiseSource, PerlinSource, SimplexSource, WorleySource};
+pub use status::ChunkStatus;
+
+use super::{Chunk, ChunkPos};
+
+/// fills freshly created chunks. `World` shares one generator between every
+/// thread that generates, so implementations must only depend on `pos` and
+/// their own settings, never on the order chunks are asked for.
+///
+/// each method is one stage of the pipeline in `ChunkStatus` order, only the
+/// base terrain is required
+pub trait TerrainGenerator: Send + Sync {
+    /// the base shape: which voxels are solid
+    fn generate_chunk(&self, chunk: &mut Chunk, pos: ChunkPos);
+
+    /// swap the top of the terrain for surface blocks and add decorations
+    fn build_surface(&self, _chunk: &mut Chunk, _pos: ChunkPos) {}
+
+    /// cut caves and ravines out of the finished surface
+    fn carve(&self, _chunk: &mut Chunk, _pos: ChunkPos) {}
+
+    /// biome of a column, for generators that have them
+    fn biome_at(&self, _x: i32, _z: i32) -> Option<&Biome> {
+        None
+    }
+
+    /// settings the generator was built from, for generators that take a config
+    fn config(&self) -> Option<&GeneratorConfig> {
+        None
+    }
+
+    /// this generator rebuilt with new settings, keeping whatever the config
+    /// doesn't cover. `None` for generators that don't take a config
+    fn reconfigure(&self, _config: GeneratorConfig) -> Option<Box<dyn TerrainGenerator>> {
+        None
+    }
+
+    /// trees, boulders and the like for a carved chunk. writes may fall in
+    /// any of the 26 neighbours but no further, `World` hands them over when
+    /// the chunk they land in is lit
+    fn place_features(&self, _chunk: &Chunk, _pos: ChunkPos) -> Vec<FeatureWrite> {
+        Vec::new()
+    }
+}
+
+/// well mixed 64 bits from a seed and a position, for decisions that must
+/// come out the same whichever chunk or thread asks
+pub(crate) fn hash(seed: u32, x: i32, y: i32, z: i32) -> u64 {
+    let mut h = seed as u64 ^ 0x9e37_79b9_7f4a_7c15;
+    for v in [x, y, z] {
+        h = (h ^ v as u32 as u64).wrapping_mul(0xff51_afd7_ed55_8ccd);
+        h ^= h >> 33;
+    }
+    h.wrapping_mul(0xc4ce_b9fe_1a85_ec53) ^ (h >> 29)
+}
+
+/// `hash` as a float in [0, 1)
+pub(crate) fn hash_unit(seed: u32, x: i32, y: i32, z: i32) -> f64 {
+    (hash(seed, x, y, z) >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// nothing at all, for worlds built by hand or loaded from regions
+#[derive(Debug, Clone, Copy, Default)]
+pub struct VoidGenerator;
+
+impl TerrainGenerator for VoidGenerator {
+    fn generate_chunk(&self, _chunk: &mut Chunk, _pos: ChunkPos) {}
+}
+
+/// one block type filling everything below `height`
+#[derive(Debug, Clone, Copy)]
+pub struct FlatGenerator {
+    pub height: i32,
+    pub block: u8,
+}
+
+impl FlatGenerator {
+    pub fn new(height: i32, block: u8) -> Self {
+        Self { height, block }
+    }
+}
+
+impl TerrainGenerator for FlatGenerator {
+    fn generate_chunk(&self, chunk: &mut Chunk, pos: ChunkPos) {
+        let base = pos.y * Chunk::SIZE as i32;
+        // rows of this chunk below the surface
+        let rows = (self.height - base).clamp(0, Chunk::SIZE as i32) as usize;
+        for y in 0..rows {
+            for z in 0..Chunk::SIZE {
+                for x in 0..Chunk::SIZE {
+                    chunk.set_block(x, y, z, self.block);
+                }
+            }
+        }
+    }
+}
+
+/// horizontal layers stacked up from y = 0, first entry at the bottom, each a
+/// block and how many voxels thick it is. air below 0 and above the last layer
+#[derive(Debug, Clone, Default)]
+pub struct SuperflatGenerator {
+    layers: Vec<(u8, u32)>,
+}
+
+impl SuperflatGenerator {
+    pub fn new(layers: Vec<(u8, u32)>) -> Self {
+        Self { layers }
+    }
+
+    pub fn layers(&self) -> &[(u8, u32)] {
+        &self.layers
+    }
+
+    /// block of the layer at world height `y`
+    fn block_at(&self, y: i32) -> u8 {
+        if y < 0 {
+            return 0;
+        }
+        let mut top = 0;
+        for &(block, thickness) in &self.layers {
+            top += thickness as i64;
+            if (y as i64) < top {
+                return block;
+            }
+        }
+        0
+    }
+}
+
+impl TerrainGenerator for SuperflatGenerator {
+    fn generate_chunk(&self, chunk: &mut Chunk, pos: ChunkPos) {
+        for y in 0..Chunk::SIZE {
+            let block = self.block_at(pos.y * Chunk::SIZE as i32 + y as i32);
+            if block == 0 {
+                continue;
+            }
+            for z in 0..Chunk::SIZE {
+                for x in 0..Chunk::SIZE {
+                    chunk.set_block(x, y, z, block);
+                }
+            }
+        }
+    }
+}
+
+/// splitmix64, small and good enough for placement rolls
+pub(crate) struct Rng(pub u64);
+
+impl Rng {
+    pub fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    pub fn below(&mut self, n: u32) -> u32 {
+        (self.next() % n as u64) as u32
+    }
+
+    /// uniform in [0, 1)
+    pub fn unit(&mut self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    pub fn range(&mut self, min: f64, max: f64) -> f64 {
+        min + (max - min) * self.unit()
+    }
+}
@@ -0,0 +1,259 @@
+use noise::core::worley::{distance_functions, worley_2d, worley_3d, ReturnType};
+use noise::permutationtable::PermutationTable;
+use noise::{Fbm, MultiFractal, NoiseFn, Perlin, Simplex, Vector2, Vector3};
+use serde::{Deserialize, Serialize};
+use super::GeneratorConfig;
+
+/// a seeded noise function the generators sample through, so the backend can
+/// be swapped without touching them. output is roughly -1 to 1, and the same
+/// seed and backend always give the same value for the same point
+pub trait NoiseSource: Send + Sync {
+    fn sample_2d(&self, x: f64, z: f64) -> f64;
+    fn sample_3d(&self, x: f64, y: f64, z: f64) -> f64;
+}
+
+/// which noise backs a generator. the fastnoise kinds only exist with the
+/// `advanced-noise` feature, configs naming them fail to load without it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NoiseKind {
+    Perlin,
+    Simplex,
+    Worley,
+    #[default]
+    Fbm,
+    #[cfg(feature = "advanced-noise")]
+    OpenSimplex2,
+    #[cfg(feature = "advanced-noise")]
+    Cellular,
+    #[cfg(feature = "advanced-noise")]
+    DomainWarp,
+}
+
+impl NoiseKind {
+    /// every kind compiled in
+    pub const ALL: &'static [Self] = &[
+        Self::Perlin,
+        Self::Simplex,
+        Self::Worley,
+        Self::Fbm,
+        #[cfg(feature = "advanced-noise")]
+        Self::OpenSimplex2,
+        #[cfg(feature = "advanced-noise")]
+        Self::Cellular,
+        #[cfg(feature = "advanced-noise")]
+        Self::DomainWarp,
+    ];
+
+    /// a source of this kind, layered per the config's octave settings where the kind supports it
+    pub fn build(self, seed: u32, config: &GeneratorConfig) -> Box<dyn NoiseSource> {
+        self.build_with(seed, config.octaves, config.lacunarity, config.persistence)
+    }
+
+    /// a source of this kind with explicit octave settings, which kinds
+    /// without octaves ignore
+    pub fn build_with(self, seed: u32, octaves: u32, lacunarity: f64, persistence: f64) -> Box<dyn NoiseSource> {
+        match self {
+            Self::Perlin => Box::new(PerlinSource::new(seed)),
+            Self::Simplex => Box::new(SimplexSource::new(seed)),
+            Self::Worley => Box::new(WorleySource::new(seed)),
+            Self::Fbm => Box::new(FbmSource::with_octaves(seed, octaves, lacunarity, persistence)),
+            #[cfg(feature = "advanced-noise")]
+            Self::OpenSimplex2 => Box::new(FastNoiseSource::open_simplex2(seed, octaves, lacunarity, persistence)),
+            #[cfg(feature = "advanced-noise")]
+            Self::Cellular => Box::new(FastNoiseSource::cellular(seed)),
+            #[cfg(feature = "advanced-noise")]
+            Self::DomainWarp => Box::new(FastNoiseSource::domain_warp(seed, octaves, lacunarity, persistence, 0.5)),
+        }
+    }
+}
+
+pub struct PerlinSource(Perlin);
+
+impl PerlinSource {
+    pub fn new(seed: u32) -> Self {
+        Self(Perlin::new(seed))
+    }
+}
+
+impl NoiseSource for PerlinSource {
+    fn sample_2d(&self, x: f64, z: f64) -> f64 {
+        self.0.get([x, z])
+    }
+
+    fn sample_3d(&self, x: f64, y: f64, z: f64) -> f64 {
+        self.0.get([x, y, z])
+    }
+}
+
+pub struct SimplexSource(Simplex);
+
+impl SimplexSource {
+    pub fn new(seed: u32) -> Self {
+        Self(Simplex::new(seed))
+    }
+}
+
+impl NoiseSource for SimplexSource {
+    fn sample_2d(&self, x: f64, z: f64) -> f64 {
+        self.0.get([x, z])
+    }
+
+    fn sample_3d(&self, x: f64, y: f64, z: f64) -> f64 {
+        self.0.get([x, y, z])
+    }
+}
+
+/// cell noise, one value per cell. calls the `noise` core directly because
+/// its `Worley` keeps the distance function in an `Rc` and can't be shared
+/// between threads
+pub struct WorleySource {
+    table: PermutationTable,
+}
+
+impl WorleySource {
+    pub fn new(seed: u32) -> Self {
+        Self { table: PermutationTable::new(seed) }
+    }
+}
+
+impl NoiseSource for WorleySource {
+    fn sample_2d(&self, x: f64, z: f64) -> f64 {
+        worley_2d(&self.table, distance_functions::euclidean, ReturnType::Value, Vector2::new(x, z))
+    }
+
+    fn sample_3d(&self, x: f64, y: f64, z: f64) -> f64 {
+        worley_3d(&self.table, distance_functions::euclidean, ReturnType::Value, Vector3::new(x, y, z))
+    }
+}
+
+/// perlin octaves summed per the config's octaves, lacunarity and persistence
+pub struct FbmSource(Fbm<Perlin>);
+
+impl FbmSource {
+    pub fn new(seed: u32, config: &GeneratorConfig) -> Self {
+        Self::with_octaves(seed, config.octaves, config.lacunarity, config.persistence)
+    }
+
+    pub fn with_octaves(seed: u32, octaves: u32, lacunarity: f64, persistence: f64) -> Self {
+        Self(
+            Fbm::<Perlin>::new(seed)
+                .set_octaves(octaves.max(1) as usize)
+                .set_lacunarity(lacunarity)
+                .set_persistence(persistence),
+        )
+    }
+}
+
+impl NoiseSource for FbmSource {
+    fn sample_2d(&self, x: f64, z: f64) -> f64 {
+        self.0.get([x, z])
+    }
+
+    fn sample_3d(&self, x: f64, y: f64, z: f64) -> f64 {
+        self.0.get([x, y, z])
+    }
+}
+
+#[cfg(feature = "advanced-noise")]
+pub use fast::FastNoiseSource;
+
+#[cfg(feature = "advanced-noise")]
+mod fast {
+    use fastnoise_lite::{CellularReturnType, DomainWarpType, FastNoiseLite, FractalType, NoiseType};
+    use super::NoiseSource;
+
+    /// FastNoiseLite, sampled at f32 precision. frequency is left at 1 since
+    /// callers scale their coordinates themselves
+    pub struct FastNoiseSource {
+        noise: FastNoiseLite,
+        /// bend the coordinates through the warp settings before sampling
+        warp: bool,
+    }
+
+    impl FastNoiseSource {
+        pub fn open_simplex2(seed: u32, octaves: u32, lacunarity: f64, persistence: f64) -> Self {
+            let mut noise = Self::base(seed, NoiseType::OpenSimplex2);
+            noise.set_fractal_type(Some(FractalType::FBm));
+            noise.set_fractal_octaves(Some(octaves.max(1) as i32));
+            noise.set_fractal_lacunarity(Some(lacunarity as f32));
+            noise.set_fractal_gain(Some(persistence as f32));
+            Self { noise, warp: false }
+        }
+
+        pub fn cellular(seed: u32) -> Self {
+            let mut noise = Self::base(seed, NoiseType::Cellular);
+            noise.set_cellular_return_type(Some(CellularReturnType::CellValue));
+            Self { noise, warp: false }
+        }
+
+        /// fractal opensimplex2 sampled through a domain warp of `amplitude`,
+        /// in the same units as the coordinates
+        pub fn domain_warp(seed: u32, octaves: u32, lacunarity: f64, persistence: f64, amplitude: f32) -> Self {
+            let mut source = Self::open_simplex2(seed, octaves, lacunarity, persistence);
+            source.noise.set_domain_warp_type(Some(DomainWarpType::OpenSimplex2));
+            source.noise.set_domain_warp_amp(Some(amplitude));
+            source.warp = true;
+            source
+        }
+
+        fn base(seed: u32, kind: NoiseType) -> FastNoiseLite {
+            let mut noise = FastNoiseLite::with_seed(seed as i32);
+            noise.set_noise_type(Some(kind));
+            noise.set_frequency(Some(1.0));
+            noise
+        }
+    }
+
+    impl NoiseSource for FastNoiseSource {
+        fn sample_2d(&self, x: f64, z: f64) -> f64 {
+            let (mut x, mut z) = (x as f32, z as f32);
+            if self.warp {
+                (x, z) = self.noise.domain_warp_2d(x, z);
+            }
+            self.noise.get_noise_2d(x, z) as f64
+        }
+
+        fn sample_3d(&self, x: f64, y: f64, z: f64) -> f64 {
+            let (mut x, mut y, mut z) = (x as f32, y as f32, z as f32);
+            if self.warp {
+                (x, y, z) = self.noise.domain_warp_3d(x, y, z);
+            }
+            self.noise.get_noise_3d(x, y, z) as f64
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn points() -> impl Iterator<Item = (f64, f64, f64)> {
+        (0..64).map(|i| {
+            let i = i as f64;
+            (i * 0.37 - 11.0, i * 0.11 + 3.0, 7.5 - i * 0.53)
+        })
+    }
+
+    #[test]
+    fn same_seed_same_values() {
+        let config = GeneratorConfig::default();
+        for &kind in NoiseKind::ALL {
+            let (a, b) = (kind.build(42, &config), kind.build(42, &config));
+            for (x, y, z) in points() {
+                assert_eq!(a.sample_2d(x, z).to_bits(), b.sample_2d(x, z).to_bits(), "{kind:?} 2d at {x}, {z}");
+                assert_eq!(a.sample_3d(x, y, z).to_bits(), b.sample_3d(x, y, z).to_bits(), "{kind:?} 3d at {x}, {y}, {z}");
+            }
+        }
+    }
+
+    #[test]
+    fn different_seeds_differ() {
+        let config = GeneratorConfig::default();
+        for &kind in NoiseKind::ALL {
+            let (a, b) = (kind.build(1, &config), kind.build(2, &config));
+            let differs = points().any(|(x, y, z)| a.sample_3d(x, y, z) != b.sample_3d(x, y, z));
+            assert!(differs, "{kind:?} ignores its seed");
+        }
+    }
+}
@@ -0,0 +1,80 @@
+/// how far through generation a chunk is: the last stage it finished.
+/// stages run in declaration order, and only `Full` chunks are handed to
+/// rendering or edits
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum ChunkStatus {
+    /// nothing run yet
+    #[default]
+    Empty,
+    /// solid or air from the generator's shape
+    BaseTerrain,
+    /// surface and filler blocks, decorations
+    Surface,
+    /// caves and ravines cut out
+    Carvers,
+    /// trees, boulders and ores decided, their writes held until each chunk
+    /// they land in is lit
+    Features,
+    /// feature writes landed and sky light flooded in from the chunks around
+    /// that are already full
+    Light,
+    /// moved into the world, ready to show. light crossing into the chunks
+    /// beside it is redone
+    Full,
+}
+
+impl ChunkStatus {
+    pub const ALL: [Self; 7] = [
+        Self::Empty,
+        Self::BaseTerrain,
+        Self::Surface,
+        Self::Carvers,
+        Self::Features,
+        Self::Light,
+        Self::Full,
+    ];
+
+    /// the stage after this one, `None` once full
+    pub fn next(self) -> Option<Self> {
+        Self::ALL.get(self as usize + 1).copied()
+    }
+
+    /// status all 26 neighbours must have reached before the stage that
+    /// produces `self` can run. features only read their own chunk, but a
+    /// chunk can't be lit before every neighbour has decided what it writes
+    /// into it
+    pub fn neighbour_requirement(self) -> Option<Self> {
+        match self {
+            Self::Light => Some(Self::Features),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stages_run_in_order() {
+        let mut status = ChunkStatus::Empty;
+        let mut seen = vec![status];
+        while let Some(next) = status.next() {
+            assert!(next > status);
+            seen.push(next);
+            status = next;
+        }
+        assert_eq!(seen, ChunkStatus::ALL);
+    }
+
+    #[test]
+    fn neighbours_are_always_behind() {
+        for status in ChunkStatus::ALL {
+            if let Some(requirement) = status.neighbour_requirement() {
+                assert!(requirement < status, "{status:?} waits on {requirement:?}");
+            }
+        }
+        assert_eq!(ChunkStatus::Light.neighbour_requirement(), Some(ChunkStatus::Features));
+        assert_eq!(ChunkStatus::Full.neighbour_requirement(), None);
+    }
+}
@@ -0,0 +1,296 @@
+use glam::{Mat3, Vec3};
+use super::{Chunk, ChunkNeighbours, ChunkPos, MeshData, MeshVertex};
+
+/// pull towards the cell's mass point; keeps flat regions stable while still
+/// letting corners and edges snap to where the planes meet
+const QEF_BIAS: f32 = 0.05;
+
+/// signed density over world space, negative inside solid terrain
+pub trait DensityField {
+    fn density(&self, p: Vec3) -> f32;
+
+    /// outward surface normal, by central differences unless overridden
+    fn gradient(&self, p: Vec3) -> Vec3 {
+        let h = 0.01;
+        Vec3::new(
+            self.density(p + Vec3::X * h) - self.density(p - Vec3::X * h),
+            self.density(p + Vec3::Y * h) - self.density(p - Vec3::Y * h),
+            self.density(p + Vec3::Z * h) - self.density(p - Vec3::Z * h),
+        )
+        .normalize_or_zero()
+    }
+
+    /// block id for the surface at `p`
+    fn material(&self, _p: Vec3) -> u8 {
+        1
+    }
+}
+
+impl<F: Fn(Vec3) -> f32> DensityField for F {
+    fn density(&self, p: Vec3) -> f32 {
+        self(p)
+    }
+}
+
+/// smooth density over the voxels of a chunk and its neighbours
+pub struct ChunkDensity<'a> {
+    neighbours: &'a ChunkNeighbours<'a>,
+    origin: [i32; 3],
+}
+
+impl<'a> ChunkDensity<'a> {
+    pub fn new(neighbours: &'a ChunkNeighbours<'a>, pos: ChunkPos) -> Self {
+        let size = Chunk::SIZE as i32;
+        Self {
+            neighbours,
+            origin: [pos.x * size, pos.y * size, pos.z * size],
+        }
+    }
+
+    /// block at world coords, which must fall inside the neighbourhood
+    fn block(&self, x: i32, y: i32, z: i32) -> u8 {
+        self.neighbours.get_block(x - self.origin[0], y - self.origin[1], z - self.origin[2])
+    }
+
+    /// share of air minus share of solid in the 8 voxels touching a lattice point
+    fn lattice(&self, x: i32, y: i32, z: i32) -> f32 {
+        let mut sum = 0.0;
+        for dz in -1..=0 {
+            for dy in -1..=0 {
+                for dx in -1..=0 {
+                    sum += if self.block(x + dx, y + dy, z + dz) != 0 { -1.0 } else { 1.0 };
+                }
+            }
+        }
+        sum / 8.0
+    }
+}
+
+impl DensityField for ChunkDensity<'_> {
+    fn density(&self, p: Vec3) -> f32 {
+        let base = p.floor();
+        let t = p - base;
+        let (x, y, z) = (base.x as i32, base.y as i32, base.z as i32);
+
+        let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+        let c = |dx, dy, dz| self.lattice(x + dx, y + dy, z + dz);
+        lerp(
+            lerp(lerp(c(0, 0, 0), c(1, 0, 0), t.x), lerp(c(0, 1, 0), c(1, 1, 0), t.x), t.y),
+            lerp(lerp(c(0, 0, 1), c(1, 0, 1), t.x), lerp(c(0, 1, 1), c(1, 1, 1), t.x), t.y),
+            t.z,
+        )
+    }
+
+    fn material(&self, p: Vec3) -> u8 {
+        // first solid voxel around the vertex, nearest first
+        let base = p.floor();
+        let mut best = (f32::MAX, 1);
+        for dz in -1..=1 {
+            for dy in -1..=1 {
+                for dx in -1..=1 {
+                    let v = base + Vec3::new(dx as f32, dy as f32, dz as f32);
+                    let block = self.block(v.x as i32, v.y as i32, v.z as i32);
+                    let dist = (v + 0.5).distance_squared(p);
+                    if block != 0 && dist < best.0 {
+                        best = (dist, block);
+                    }
+                }
+            }
+        }
+        best.1
+    }
+}
+
+/// dual contouring over one chunk's cells. `field` is sampled in world space
+/// and the mesh comes back chunk-local.
+///
+/// a chunk emits quads only for edges starting inside it, and vertices for
+/// cells one past its low faces are solved from the same world-space samples
+/// its neighbour uses, so adjacent chunks meet without cracks
+pub fn dual_contour(field: &impl DensityField, pos: ChunkPos) -> MeshData {
+    let size = Chunk::SIZE as i32;
+    let origin = Vec3::new(
+        (pos.x * size) as f32,
+        (pos.y * size) as f32,
+        (pos.z * size) as f32,
+    );
+
+    // densities at lattice points -1..=size on each axis
+    let samples_per_axis = (size + 2) as usize;
+    let sample_index = |x: i32, y: i32, z: i32| {
+        ((z + 1) as usize * samples_per_axis + (y + 1) as usize) * samples_per_axis + (x + 1) as usize
+    };
+    let mut samples = vec![0.0; samples_per_axis.pow(3)];
+    for z in -1..=size {
+        for y in -1..=size {
+            for x in -1..=size {
+                samples[sample_index(x, y, z)] =
+                    field.density(origin + Vec3::new(x as f32, y as f32, z as f32));
+            }
+        }
+    }
+    let density = |p: [i32; 3]| samples[sample_index(p[0], p[1], p[2])];
+
+    // one vertex per sign-changing cell, cells -1..size-1 on each axis
+    let cells_per_axis = (size + 1) as usize;
+    let cell_index = |p: [i32; 3]| {
+        ((p[2] + 1) as usize * cells_per_axis + (p[1] + 1) as usize) * cells_per_axis + (p[0] + 1) as usize
+    };
+    let mut cell_vertex: Vec<Option<u32>> = vec![None; cells_per_axis.pow(3)];
+    let mut mesh = MeshData::default();
+
+    for z in -1..size {
+        for y in -1..size {
+            for x in -1..size {
+                let cell = [x, y, z];
+                let Some(local) = solve_cell(field, origin, cell, &density) else {
+                    continue;
+                };
+
+                let world = origin + local;
+                cell_vertex[cell_index(cell)] = Some(mesh.vertices.len() as u32);
+                mesh.vertices.push(MeshVertex {
+                    position: local.to_array(),
+                    normal: field.gradient(world).to_array(),
+                    block: field.material(world) as u32,
+                    ao: 1.0,
+                });
+            }
+        }
+    }
+
+    // a quad around every sign-changing edge that starts inside this chunk
+    for z in 0..size {
+        for y in 0..size {
+            for x in 0..size {
+                let start = [x, y, z];
+                for axis in 0..3 {
+                    let mut end = start;
+                    end[axis] += 1;
+                    let (d0, d1) = (density(start), density(end));
+                    if (d0 < 0.0) == (d1 < 0.0) {
+                        continue;
+                    }
+
+                    let u = (axis + 1) % 3;
+                    let v = (axis + 2) % 3;
+                    let cell = |du: i32, dv: i32| {
+                        let mut c = start;
+                        c[u] -= du;
+                        c[v] -= dv;
+                        cell_vertex[cell_index(c)]
+                    };
+
+                    let (Some(a), Some(b), Some(c), Some(d)) = (cell(1, 1), cell(0, 1), cell(0, 0), cell(1, 0)) else {
+                        continue;
+                    };
+
+                    // counter-clockwise seen from +axis; flip when the solid side is at the end
+                    let quad = if d0 < 0.0 { [a, b, c, d] } else { [d, c, b, a] };
+                    mesh.indices.extend([quad[0], quad[1], quad[2], quad[0], quad[2], quad[3]]);
+                }
+            }
+        }
+    }
+
+    mesh
+}
+
+/// minimise the quadratic error of the cell's edge-crossing planes.
+/// returns the vertex relative to the chunk origin
+fn solve_cell(
+    field: &impl DensityField,
+    origin: Vec3,
+    cell: [i32; 3],
+    density: &impl Fn([i32; 3]) -> f32,
+) -> Option<Vec3> {
+    const EDGES: [([i32; 3], [i32; 3]); 12] = [
+        ([0, 0, 0], [1, 0, 0]), ([0, 1, 0], [1, 1, 0]), ([0, 0, 1], [1, 0, 1]), ([0, 1, 1], [1, 1, 1]),
+        ([0, 0, 0], [0, 1, 0]), ([1, 0, 0], [1, 1, 0]), ([0, 0, 1], [0, 1, 1]), ([1, 0, 1], [1, 1, 1]),
+        ([0, 0, 0], [0, 0, 1]), ([1, 0, 0], [1, 0, 1]), ([0, 1, 0], [0, 1, 1]), ([1, 1, 0], [1, 1, 1]),
+    ];
+
+    let corner = Vec3::new(cell[0] as f32, cell[1] as f32, cell[2] as f32);
+    let mut ata = Mat3::ZERO;
+    let mut atb = Vec3::ZERO;
+    let mut mass = Vec3::ZERO;
+    let mut count = 0;
+
+    for (a, b) in EDGES {
+        let pa = [cell[0] + a[0], cell[1] + a[1], cell[2] + a[2]];
+        let pb = [cell[0] + b[0], cell[1] + b[1], cell[2] + b[2]];
+        let (da, db) = (density(pa), density(pb));
+        if (da < 0.0) == (db < 0.0) {
+            continue;
+        }
+
+        // crossing in cell-local space, so neighbouring chunks solve identical systems
+        let t = da / (da - db);
+        let la = Vec3::new(a[0] as f32, a[1] as f32, a[2] as f32);
+        let lb = Vec3::new(b[0] as f32, b[1] as f32, b[2] as f32);
+        let p = la + (lb - la) * t;
+        let n = field.gradient(origin + corner + p);
+
+        ata += Mat3::from_cols(n * n.x, n * n.y, n * n.z);
+        atb += n * n.dot(p);
+        mass += p;
+        count += 1;
+    }
+
+    if count == 0 {
+        return None;
+    }
+
+    let mass = mass / count as f32;
+    let lhs = ata + Mat3::from_diagonal(Vec3::splat(QEF_BIAS));
+    let rhs = atb + mass * QEF_BIAS;
+    let solved = lhs.inverse() * rhs;
+
+    // features the planes can't resolve would otherwise shoot out of the cell
+    let solved = if solved.is_finite() { solved.clamp(Vec3::ZERO, Vec3::ONE) } else { mass };
+    Some(corner + solved)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// a wavy ball straddling the face between chunks 0 and 1 along x
+    fn blob(p: Vec3) -> f32 {
+        let centre = Vec3::new(Chunk::SIZE as f32, 12.3, 15.7);
+        p.distance(centre) - 9.0 + (p.x * 0.7).sin() * (p.y * 0.5).cos() * 1.5
+    }
+
+    /// world positions of the vertices in the cells straddling x = `SIZE`
+    fn seam(pos: ChunkPos) -> Vec<[f32; 3]> {
+        let origin = Vec3::new((pos.x * Chunk::SIZE as i32) as f32, 0.0, 0.0);
+        let edge = Chunk::SIZE as f32;
+        let mut seam: Vec<[f32; 3]> = dual_contour(&blob, pos)
+            .vertices
+            .iter()
+            .map(|v| origin + Vec3::from_array(v.position))
+            .filter(|p| p.x > edge - 1.0 && p.x < edge)
+            .map(|p| p.to_array())
+            .collect();
+        seam.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        seam
+    }
+
+    #[test]
+    fn neighbours_share_seam_vertices() {
+        let low = seam(ChunkPos { x: 0, y: 0, z: 0 });
+        assert!(low.len() > 10, "the blob should cross the seam");
+        assert_eq!(low, seam(ChunkPos { x: 1, y: 0, z: 0 }));
+    }
+
+    #[test]
+    fn seam_quads_are_not_doubled() {
+        let quads = |pos| dual_contour(&blob, pos).indices.len() / 6;
+        let low = quads(ChunkPos { x: 0, y: 0, z: 0 });
+        let high = quads(ChunkPos { x: 1, y: 0, z: 0 });
+        // the same surface meshed as one piece, shifted so it sits inside a chunk
+        let shift = Vec3::X * (Chunk::SIZE / 2) as f32;
+        let whole = dual_contour(&|p: Vec3| blob(p + shift), ChunkPos { x: 0, y: 0, z: 0 });
+        assert_eq!(low + high, whole.indices.len() / 6);
+    }
+}
@@ -0,0 +1,276 @@
+use bytemuck::{Pod, Zeroable};
+use super::Chunk;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Pod, Zeroable)]
+pub struct MeshVertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    pub block: u32,
+    /// 0 fully occluded, 1 fully open
+    pub ao: f32,
+}
+
+/// cpu-side triangle mesh, chunk-local positions
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MeshData {
+    pub vertices: Vec<MeshVertex>,
+    pub indices: Vec<u32>,
+}
+
+impl MeshData {
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+}
+
+/// a chunk and the 26 chunks around it. missing neighbours read as air
+pub struct ChunkNeighbours<'a> {
+    chunks: [Option<&'a Chunk>; 27],
+}
+
+impl<'a> ChunkNeighbours<'a> {
+    pub fn new(center: &'a Chunk) -> Self {
+        let mut chunks = [None; 27];
+        chunks[13] = Some(center);
+        Self { chunks }
+    }
+
+    /// set the neighbour at offset (dx, dy, dz), each in -1..=1
+    pub fn set(&mut self, dx: i32, dy: i32, dz: i32, chunk: &'a Chunk) {
+        self.chunks[Self::slot(dx, dy, dz)] = Some(chunk);
+    }
+
+    /// block at coords relative to the centre chunk, may reach one chunk outside it
+    pub fn get_block(&self, x: i32, y: i32, z: i32) -> u8 {
+        let size = Chunk::SIZE as i32;
+        let slot = Self::slot(x.div_euclid(size), y.div_euclid(size), z.div_euclid(size));
+        self.chunks[slot].map_or(0, |chunk| {
+            chunk.get_block(
+                x.rem_euclid(size) as usize,
+                y.rem_euclid(size) as usize,
+                z.rem_euclid(size) as usize,
+            )
+        })
+    }
+
+    fn slot(dx: i32, dy: i32, dz: i32) -> usize {
+        ((dz + 1) * 9 + (dy + 1) * 3 + (dx + 1)) as usize
+    }
+}
+
+/// what a face looks like; only identical faces are merged
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Face {
+    block: u8,
+    ao: [u8; 4],
+}
+
+/// greedy mesher: merges coplanar faces with the same block and ao into single quads
+pub fn mesh_chunk(neighbours: &ChunkNeighbours) -> MeshData {
+    let size = Chunk::SIZE as i32;
+    let mut mesh = MeshData::default();
+    let mut mask: Vec<Option<Face>> = vec![None; Chunk::SIZE * Chunk::SIZE];
+
+    for axis in 0..3 {
+        let u = (axis + 1) % 3;
+        let v = (axis + 2) % 3;
+
+        for front in [false, true] {
+            let dir = if front { 1 } else { -1 };
+
+            for slice in 0..size {
+                // visible faces in this slice
+                for j in 0..size {
+                    for i in 0..size {
+                        let mut pos = [0; 3];
+                        pos[axis] = slice;
+                        pos[u] = i;
+                        pos[v] = j;
+
+                        let block = neighbours.get_block(pos[0], pos[1], pos[2]);
+                        let mut facing = pos;
+                        facing[axis] += dir;
+
+                        mask[(j * size + i) as usize] = if block != 0
+                            && neighbours.get_block(facing[0], facing[1], facing[2]) == 0
+                        {
+                            Some(Face {
+                                block,
+                                ao: face_ao(neighbours, facing, u, v),
+                            })
+                        } else {
+                            None
+                        };
+                    }
+                }
+
+                // merge into rectangles, widest first
+                for j in 0..size {
+                    let mut i = 0;
+                    while i < size {
+                        let Some(face) = mask[(j * size + i) as usize] else {
+                            i += 1;
+                            continue;
+                        };
+
+                        let mut width = 1;
+                        while i + width < size && mask[(j * size + i + width) as usize] == Some(face) {
+                            width += 1;
+                        }
+
+                        let mut height = 1;
+                        'grow: while j + height < size {
+                            for k in 0..width {
+                                if mask[((j + height) * size + i + k) as usize] != Some(face) {
+                                    break 'grow;
+                                }
+                            }
+                            height += 1;
+                        }
+
+                        for dj in 0..height {
+                            for di in 0..width {
+                                mask[((j + dj) * size + i + di) as usize] = None;
+                            }
+                        }
+
+                        push_quad(&mut mesh, face, axis, front, slice, i, j, width, height);
+                        i += width;
+                    }
+                }
+            }
+        }
+    }
+
+    mesh
+}
+
+/// per-corner ambient occlusion for a face looking into `facing`, corners in
+/// (-u,-v), (+u,-v), (+u,+v), (-u,+v) order
+fn face_ao(neighbours: &ChunkNeighbours, facing: [i32; 3], u: usize, v: usize) -> [u8; 4] {
+    let solid = |du: i32, dv: i32| {
+        let mut p = facing;
+        p[u] += du;
+        p[v] += dv;
+        (neighbours.get_block(p[0], p[1], p[2]) != 0) as u8
+    };
+
+    [(-1, -1), (1, -1), (1, 1), (-1, 1)].map(|(du, dv)| {
+        let side_u = solid(du, 0);
+        let side_v = solid(0, dv);
+        if side_u == 1 && side_v == 1 {
+            0
+        } else {
+            3 - (side_u + side_v + solid(du, dv))
+        }
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+fn push_quad(
+    mesh: &mut MeshData,
+    face: Face,
+    axis: usize,
+    front: bool,
+    slice: i32,
+    i: i32,
+    j: i32,
+    width: i32,
+    height: i32,
+) {
+    let u = (axis + 1) % 3;
+    let v = (axis + 2) % 3;
+
+    let mut normal = [0.0; 3];
+    normal[axis] = if front { 1.0 } else { -1.0 };
+
+    let plane = (slice + front as i32) as f32;
+    let corners = [(i, j), (i + width, j), (i + width, j + height), (i, j + height)];
+
+    let base = mesh.vertices.len() as u32;
+    for (corner, &(cu, cv)) in corners.iter().enumerate() {
+        let mut position = [0.0; 3];
+        position[axis] = plane;
+        position[u] = cu as f32;
+        position[v] = cv as f32;
+        mesh.vertices.push(MeshVertex {
+            position,
+            normal,
+            block: face.block as u32,
+            ao: face.ao[corner] as f32 / 3.0,
+        });
+    }
+
+    // split along the brighter diagonal so ao interpolates without creasing
+    let ao = face.ao;
+    let quad: [u32; 6] = if ao[0] as u32 + ao[2] as u32 >= ao[1] as u32 + ao[3] as u32 {
+        [0, 1, 2, 0, 2, 3]
+    } else {
+        [1, 2, 3, 1, 3, 0]
+    };
+
+    // u x v points along +axis, so corners are counter-clockwise for front faces
+    if front {
+        mesh.indices.extend(quad.iter().map(|&k| base + k));
+    } else {
+        mesh.indices.extend(quad.iter().rev().map(|&k| base + k));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quads(mesh: &MeshData) -> usize {
+        mesh.vertices.len() / 4
+    }
+
+    #[test]
+    fn lone_block_is_six_open_quads() {
+        let mut chunk = Chunk::new();
+        chunk.set_block(4, 4, 4, 1);
+        let mesh = mesh_chunk(&ChunkNeighbours::new(&chunk));
+        assert_eq!(quads(&mesh), 6);
+        assert_eq!(mesh.indices.len(), 6 * 6);
+        assert!(mesh.vertices.iter().all(|v| v.block == 1 && v.ao == 1.0));
+    }
+
+    #[test]
+    fn matching_faces_merge() {
+        let mut chunk = Chunk::new();
+        for x in 0..4 {
+            chunk.set_block(x, 0, 0, 2);
+        }
+        assert_eq!(quads(&mesh_chunk(&ChunkNeighbours::new(&chunk))), 6);
+
+        chunk.set_block(3, 0, 0, 3);
+        assert_eq!(quads(&mesh_chunk(&ChunkNeighbours::new(&chunk))), 10);
+    }
+
+    #[test]
+    fn faces_against_neighbours_are_culled() {
+        let mut chunk = Chunk::new();
+        chunk.set_block(Chunk::SIZE - 1, 0, 0, 1);
+        let mut next = Chunk::new();
+        next.set_block(0, 0, 0, 1);
+
+        let mut neighbours = ChunkNeighbours::new(&chunk);
+        assert_eq!(quads(&mesh_chunk(&neighbours)), 6);
+        neighbours.set(1, 0, 0, &next);
+        let mesh = mesh_chunk(&neighbours);
+        assert_eq!(quads(&mesh), 5);
+        assert!(mesh.vertices.iter().all(|v| v.normal != [1.0, 0.0, 0.0]));
+    }
+
+    #[test]
+    fn corners_next_to_blocks_are_darker() {
+        let mut chunk = Chunk::new();
+        chunk.set_block(4, 4, 4, 1);
+        chunk.set_block(5, 5, 4, 1);
+        let mesh = mesh_chunk(&ChunkNeighbours::new(&chunk));
+        let top = mesh.vertices.iter().filter(|v| v.normal == [0.0, 1.0, 0.0] && v.position[1] == 5.0);
+        assert!(top.clone().any(|v| v.ao < 1.0));
+        assert!(top.clone().any(|v| v.ao == 1.0));
+    }
+}
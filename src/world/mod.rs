@@ -1,16 +1,76 @@
+pub mod block;
+mod brickmap;
 mod chunk;
+mod dag;
 mod generate;
+mod isosurface;
+mod mesher;
+mod octree;
+mod region;
 
-pub use chunk::Chunk;
-pub use generate::WorldGenerator;
+pub use brickmap::{Brickmap, BRICK_SIZE, BRICK_VOLUME, EMPTY_BRICK};
+pub use chunk::{Chunk, DistanceMetric};
+pub use dag::SparseVoxelDag;
+pub use generate::{
+    default_biomes, default_rules, Biome, BiomeBlend, BiomeMap, BlockConfig, CarverParams, CaveCarver, ChunkStatus,
+    ConfigError, Decoration, DensityGenerator, DensityParams, Eroder, ErosionParams, FbmSource, Feature,
+    FeaturePlacer, FeatureRule, FeatureWrite, FlatGenerator, GeneratorConfig, NoiseKind, NoiseSource, PerlinSource,
+    Replace, SimplexSource, SuperflatGenerator, TerrainGenerator, VoidGenerator, WorldGenerator, WorleySource,
+    CARVER_REGION, EROSION_REGION,
+};
+#[cfg(feature = "advanced-noise")]
+pub use generate::FastNoiseSource;
+pub use isosurface::{dual_contour, ChunkDensity, DensityField};
+pub use mesher::{mesh_chunk, ChunkNeighbours, MeshData, MeshVertex};
+pub use octree::{OctreeHit, OctreeNode, SparseVoxelOctree, OCTREE_LEAF, OCTREE_MIXED};
+pub use region::Region;
 
-use parking_lot::RwLock;
-use std::collections::HashMap;
+use glam::{IVec3, Vec3};
+use parking_lot::{Condvar, Mutex, RwLock};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Arc;
+use crate::utils::math::Vec3f;
+use crate::utils::ray::{Ray, RaycastHit, VoxelRayResult};
 
 pub struct World {
     chunks: RwLock<HashMap<ChunkPos, Chunk>>,
-    generate: WorldGenerator,
+    /// swapped out whole when settings change, stages still running on the old one are thrown away
+    generate: RwLock<Arc<dyn TerrainGenerator>>,
     seed: u32,
+    /// chunks generated, edited, or unloaded since the last `take_dirty`
+    dirty: Mutex<HashSet<ChunkPos>>,
+    /// built on first use, dropped whenever their chunk changes
+    octrees: Mutex<HashMap<ChunkPos, SparseVoxelOctree>>,
+    /// chunks partway through generation. locked only to pick a chunk up or
+    /// put it down, never while a stage runs
+    staging: Mutex<Staging>,
+    /// signalled whenever a stage puts its chunk back
+    staged: Condvar,
+}
+
+struct Staging {
+    /// chunks partway through generation, never shown or edited
+    proto: HashMap<ChunkPos, ProtoChunk>,
+    /// feature writes waiting for their target chunk to be lit, by target
+    /// then source chunk. only chunks in `proto` have an entry, it goes when
+    /// they are lit or dropped
+    pending: HashMap<ChunkPos, HashMap<ChunkPos, Vec<FeatureWrite>>>,
+    /// bumped when the generator is swapped, stages started before are thrown away
+    epoch: u64,
+}
+
+/// a chunk still going through the generation stages
+struct ProtoChunk {
+    /// `None` while a thread has it out running a stage
+    chunk: Option<Chunk>,
+    /// the last stage finished
+    status: ChunkStatus,
+}
+
+impl ProtoChunk {
+    fn new() -> Self {
+        Self { chunk: Some(Chunk::new()), status: ChunkStatus::Empty }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -29,14 +89,43 @@ impl ChunkPos {
             z: z.div_euclid(Chunk::SIZE as i32),
         }
     }
+
+    // world coords of the chunk's low corner
+    pub fn origin(self) -> IVec3 {
+        IVec3::new(self.x, self.y, self.z) * Chunk::SIZE as i32
+    }
+
+    // the 6 chunks sharing a face with this one, -x, +x, -y, +y, -z, +z
+    pub fn faces(self) -> [ChunkPos; 6] {
+        [(-1, 0, 0), (1, 0, 0), (0, -1, 0), (0, 1, 0), (0, 0, -1), (0, 0, 1)]
+            .map(|(dx, dy, dz)| ChunkPos { x: self.x + dx, y: self.y + dy, z: self.z + dz })
+    }
+
+    // the 26 chunks touching this one
+    pub fn neighbours(self) -> impl Iterator<Item = ChunkPos> {
+        (-1..=1).flat_map(move |dz| (-1..=1).flat_map(move |dy| (-1..=1).map(move |dx| (dx, dy, dz))))
+            .filter(|&offset| offset != (0, 0, 0))
+            .map(move |(dx, dy, dz)| ChunkPos { x: self.x + dx, y: self.y + dy, z: self.z + dz })
+    }
 }
 
 impl World {
+    // a world of noise heightmap terrain
     pub fn new(seed: u32) -> Self {
+        Self::with_generator(seed, WorldGenerator::new(seed))
+    }
+
+    // a world filled by any generator. `seed` is only recorded, generators
+    // that want one take it themselves
+    pub fn with_generator(seed: u32, generator: impl TerrainGenerator + 'static) -> Self {
         Self {
             chunks: RwLock::new(HashMap::new()),
-            generate: WorldGenerator::new(seed),
+            generate: RwLock::new(Arc::new(generator)),
             seed,
+            dirty: Mutex::new(HashSet::new()),
+            octrees: Mutex::new(HashMap::new()),
+            staging: Mutex::new(Staging { proto: HashMap::new(), pending: HashMap::new(), epoch: 0 }),
+            staged: Condvar::new(),
         }
     }
 
@@ -52,11 +141,423 @@ impl World {
         })
     }
 
-    // generate chunk at specified position
+    // set block at world coords, false if its chunk isn't loaded
+    pub fn set_block(&self, x: i32, y: i32, z: i32, block: u8) -> bool {
+        let chunk_pos = ChunkPos::from_world(x, y, z);
+        let mut chunks = self.chunks.write();
+        let Some(chunk) = chunks.get_mut(&chunk_pos) else {
+            return false;
+        };
+        chunk.set_block(
+            x.rem_euclid(Chunk::SIZE as i32) as usize,
+            y.rem_euclid(Chunk::SIZE as i32) as usize,
+            z.rem_euclid(Chunk::SIZE as i32) as usize,
+            block
+        );
+        Self::relight(&mut chunks, [chunk_pos]);
+        self.octrees.lock().remove(&chunk_pos);
+        self.dirty.lock().insert(chunk_pos);
+        true
+    }
+
+    // relight chunks, then the loaded chunks beside any whose light changed,
+    // for as long as it keeps changing, e.g. when an edit opens a shaft
+    fn relight(chunks: &mut HashMap<ChunkPos, Chunk>, start: impl IntoIterator<Item = ChunkPos>) {
+        let mut queue: VecDeque<ChunkPos> = start.into_iter().collect();
+        while let Some(pos) = queue.pop_front() {
+            let Some(mut chunk) = chunks.remove(&pos) else {
+                continue;
+            };
+            let changed = chunk.rebuild_sky_light(pos.faces().map(|face| chunks.get(&face)));
+            chunks.insert(pos, chunk);
+            if changed {
+                for face in pos.faces() {
+                    if chunks.contains_key(&face) && !queue.contains(&face) {
+                        queue.push_back(face);
+                    }
+                }
+            }
+        }
+    }
+
+    // a world of heightmap terrain shaped by `config`, e.g. one loaded with
+    // `GeneratorConfig::load`
+    pub fn with_config(seed: u32, config: GeneratorConfig) -> Self {
+        Self::with_generator(seed, WorldGenerator::with_config(seed, config))
+    }
+
+    // run a chunk through every generation stage and make it visible. stages
+    // that need neighbours pull those far enough along first, so a ring of
+    // part-generated chunks builds up around what is loaded. any number of
+    // threads may generate at once, each stage only locks its own chunk
     pub fn generate_chunk(&self, pos: ChunkPos) {
+        // a generator swap partway abandons the work, start again with the new one
+        loop {
+            let (generator, epoch) = {
+                let staging = self.staging.lock();
+                (self.generator(), staging.epoch)
+            };
+            if self.advance(generator.as_ref(), epoch, pos, ChunkStatus::Full) {
+                return;
+            }
+        }
+    }
+
+    // how far along generation a chunk is, `Empty` if it was never started
+    pub fn chunk_status(&self, pos: ChunkPos) -> ChunkStatus {
+        let staging = self.staging.lock();
+        if self.chunks.read().contains_key(&pos) {
+            return ChunkStatus::Full;
+        }
+        staging.proto.get(&pos).map_or(ChunkStatus::Empty, |proto| proto.status)
+    }
+
+    // false if the generator was swapped since `epoch`
+    fn advance(&self, generator: &dyn TerrainGenerator, epoch: u64, pos: ChunkPos, target: ChunkStatus) -> bool {
+        loop {
+            let next = {
+                let staging = self.staging.lock();
+                if staging.epoch != epoch {
+                    return false;
+                }
+                if self.chunks.read().contains_key(&pos) {
+                    return true;
+                }
+                let status = staging.proto.get(&pos).map_or(ChunkStatus::Empty, |proto| proto.status);
+                match status.next().filter(|_| status < target) {
+                    Some(next) => next,
+                    None => return true,
+                }
+            };
+            // requirements are always an earlier status than the stage, so this bottoms out
+            if let Some(required) = next.neighbour_requirement() {
+                for neighbour in pos.neighbours() {
+                    if !self.advance(generator, epoch, neighbour, required) {
+                        return false;
+                    }
+                }
+            }
+            if !self.run_stage(generator, epoch, pos, next) {
+                return false;
+            }
+        }
+    }
+
+    // run one stage on a chunk. the chunk is taken out of `proto` while the
+    // stage runs, so other threads only wait on it if they want this chunk
+    fn run_stage(&self, generator: &dyn TerrainGenerator, epoch: u64, pos: ChunkPos, stage: ChunkStatus) -> bool {
+        let mut chunk = {
+            let mut staging = self.staging.lock();
+            loop {
+                if staging.epoch != epoch {
+                    return false;
+                }
+                if self.chunks.read().contains_key(&pos) {
+                    return true;
+                }
+                let entry = staging.proto.entry(pos).or_insert_with(ProtoChunk::new);
+                // another thread got there first, or the chunk was dropped; `advance` looks again
+                if entry.status.next() != Some(stage) {
+                    return true;
+                }
+                if let Some(chunk) = entry.chunk.take() {
+                    break chunk;
+                }
+                self.staged.wait(&mut staging);
+            }
+        };
+
+        let mut writes = None;
+        match stage {
+            ChunkStatus::Empty => {}
+            ChunkStatus::BaseTerrain => generator.generate_chunk(&mut chunk, pos),
+            ChunkStatus::Surface => generator.build_surface(&mut chunk, pos),
+            ChunkStatus::Carvers => generator.carve(&mut chunk, pos),
+            ChunkStatus::Features => writes = Some(generator.place_features(&chunk, pos)),
+            ChunkStatus::Light => {
+                if !self.light(generator, epoch, &mut chunk, pos) {
+                    return false;
+                }
+            }
+            ChunkStatus::Full => return self.finish(epoch, chunk, pos),
+        }
+
+        let mut staging = self.staging.lock();
+        if staging.epoch != epoch {
+            return false;
+        }
+        if let Some(writes) = writes {
+            self.hold_features(&mut staging, pos, writes);
+        }
+        // busy entries are never dropped, so it is still there
+        if let Some(entry) = staging.proto.get_mut(&pos) {
+            entry.chunk = Some(chunk);
+            entry.status = stage;
+        }
+        self.staged.notify_all();
+        true
+    }
+
+    // land the feature writes held for a chunk and light it from the full
+    // chunks around. false if the generator was swapped meanwhile
+    fn light(&self, generator: &dyn TerrainGenerator, epoch: u64, chunk: &mut Chunk, pos: ChunkPos) -> bool {
+        let held = {
+            let mut staging = self.staging.lock();
+            if staging.epoch != epoch {
+                return false;
+            }
+            staging.pending.remove(&pos).unwrap_or_default()
+        };
+        Self::apply_features(generator, chunk, pos, held);
+
+        let chunks = self.chunks.read();
+        chunk.rebuild_sky_light(pos.faces().map(|face| chunks.get(&face)));
+        chunk.rebuild_distance_field();
+        true
+    }
+
+    // the last stage: moved over to `chunks`, then it and the chunks beside
+    // it relit, now light can cross between them
+    fn finish(&self, epoch: u64, chunk: Chunk, pos: ChunkPos) -> bool {
+        if self.staging.lock().epoch != epoch {
+            return false;
+        }
+        {
+            let mut chunks = self.chunks.write();
+            chunks.insert(pos, chunk);
+            Self::relight(&mut chunks, std::iter::once(pos).chain(pos.faces()));
+        }
+        self.octrees.lock().remove(&pos);
+        self.dirty.lock().insert(pos);
+
+        // the proto entry stays taken until the chunk is in `chunks`, so
+        // nobody sees it missing from both and starts it over
+        let mut staging = self.staging.lock();
+        if staging.epoch == epoch {
+            staging.proto.remove(&pos);
+            staging.pending.remove(&pos);
+        }
+        self.staged.notify_all();
+        true
+    }
+
+    // keep a chunk's feature writes until each chunk they land in is lit,
+    // an empty list for targets it leaves alone. only targets still in
+    // `proto` keep them; a lit target already got them, and a dropped one
+    // works them out again
+    fn hold_features(&self, staging: &mut Staging, pos: ChunkPos, writes: Vec<FeatureWrite>) {
+        let mut outgoing: HashMap<ChunkPos, Vec<FeatureWrite>> =
+            std::iter::once(pos).chain(pos.neighbours()).map(|target| (target, Vec::new())).collect();
+        for write in writes {
+            // nothing reaches further than a neighbour, see `TerrainGenerator::place_features`
+            if let Some(list) = outgoing.get_mut(&ChunkPos::from_world(write.pos.x, write.pos.y, write.pos.z)) {
+                list.push(write);
+            }
+        }
+
+        for (target, writes) in outgoing {
+            if staging.proto.contains_key(&target) {
+                staging.pending.entry(target).or_default().insert(pos, writes);
+            }
+        }
+    }
+
+    // apply every feature write landing in a chunk, from itself and its
+    // neighbours. `Replace::Only` and equal ranks don't commute, so writes go
+    // in by source position and then in the order they were placed, never in
+    // the order sources happened to generate
+    fn apply_features(
+        generator: &dyn TerrainGenerator,
+        chunk: &mut Chunk,
+        pos: ChunkPos,
+        mut held: HashMap<ChunkPos, Vec<FeatureWrite>>,
+    ) {
+        let mut sources: Vec<ChunkPos> = std::iter::once(pos).chain(pos.neighbours()).collect();
+        sources.sort_by_key(|source| (source.x, source.y, source.z));
+        for source in sources {
+            let writes = held.remove(&source).unwrap_or_else(|| Self::replay_features(generator, source, pos));
+            for write in &writes {
+                write.apply(chunk, pos.origin());
+            }
+        }
+    }
+
+    // writes `source` makes into `target` when they weren't held, because the
+    // source finished or was dropped before the target got this far.
+    // placement only depends on the source's carved terrain, so that is
+    // built again
+    fn replay_features(generator: &dyn TerrainGenerator, source: ChunkPos, target: ChunkPos) -> Vec<FeatureWrite> {
         let mut chunk = Chunk::new();
-        self.generator.generate_chunk(&mut chunk, pos);
-        self.chunks.write().insert(pos, chunk);
+        generator.generate_chunk(&mut chunk, source);
+        generator.build_surface(&mut chunk, source);
+        generator.carve(&mut chunk, source);
+        let mut writes = generator.place_features(&chunk, source);
+        writes.retain(|write| ChunkPos::from_world(write.pos.x, write.pos.y, write.pos.z) == target);
+        writes
+    }
+
+    // drop a chunk from memory, along with part-generated chunks around it
+    // that no longer border anything loaded
+    pub fn unload_chunk(&self, pos: ChunkPos) -> Option<Chunk> {
+        let chunk = self.chunks.write().remove(&pos);
+        self.octrees.lock().remove(&pos);
+        if chunk.is_some() {
+            self.dirty.lock().insert(pos);
+        }
+
+        let mut staging = self.staging.lock();
+        let chunks = self.chunks.read();
+        let near_full = |p: ChunkPos| std::iter::once(p).chain(p.neighbours()).any(|n| chunks.contains_key(&n));
+        for stale in std::iter::once(pos).chain(pos.neighbours()) {
+            // taken entries are mid-stage, their thread puts them back
+            let idle = staging.proto.get(&stale).is_some_and(|proto| proto.chunk.is_some());
+            if idle && !near_full(stale) {
+                staging.proto.remove(&stale);
+                staging.pending.remove(&stale);
+            }
+        }
+        chunk
+    }
+
+    // chunks that changed since the last call. unloaded ones are included, check `is_loaded`
+    pub fn take_dirty(&self) -> Vec<ChunkPos> {
+        self.dirty.lock().drain().collect()
+    }
+
+    pub fn is_loaded(&self, pos: ChunkPos) -> bool {
+        self.chunks.read().contains_key(&pos)
+    }
+
+    // copy the loaded chunks between two corners, inclusive, e.g. to save
+    // them. the generator's settings go along when it has any
+    pub fn export_region(&self, min: ChunkPos, max: ChunkPos) -> Region {
+        let chunks = self.chunks.read();
+        let mut region = Region::new();
+        region.set_config(self.generator_config());
+        for (pos, chunk) in chunks.iter() {
+            let inside = (min.x..=max.x).contains(&pos.x)
+                && (min.y..=max.y).contains(&pos.y)
+                && (min.z..=max.z).contains(&pos.z);
+            if inside {
+                region.insert(*pos, chunk.clone());
+            }
+        }
+        region
+    }
+
+    // load a region's chunks, replacing any already there, and apply the
+    // generator settings saved with it. fails without importing anything if
+    // the current generator can't take them; clear the region's config to
+    // keep the generator as it is
+    pub fn import_region(&self, region: Region) -> Result<(), ConfigError> {
+        if let Some(config) = region.config() {
+            self.apply_generator_config(config.clone())?;
+        }
+
+        let mut chunks = self.chunks.write();
+        let mut octrees = self.octrees.lock();
+        let mut dirty = self.dirty.lock();
+        let mut imported = Vec::new();
+        for (pos, chunk) in region.into_chunks() {
+            chunks.insert(pos, chunk);
+            octrees.remove(&pos);
+            dirty.insert(pos);
+            imported.push(pos);
+        }
+        // top down, so most chunks see the final light above them first,
+        // then the chunks already loaded beside them
+        imported.sort_by_key(|pos| std::cmp::Reverse(pos.y));
+        let beside: Vec<ChunkPos> = imported.iter().flat_map(|pos| pos.faces()).collect();
+        Self::relight(&mut chunks, imported.into_iter().chain(beside));
+        Ok(())
+    }
+
+    // run `f` on a loaded chunk without cloning it
+    pub fn with_chunk<R>(&self, pos: ChunkPos, f: impl FnOnce(&Chunk) -> R) -> Option<R> {
+        self.chunks.read().get(&pos).map(f)
+    }
+
+    // run `f` on a loaded chunk's octree, building it if the chunk changed since last time
+    pub fn with_octree<R>(&self, pos: ChunkPos, f: impl FnOnce(&SparseVoxelOctree) -> R) -> Option<R> {
+        let chunks = self.chunks.read();
+        let chunk = chunks.get(&pos)?;
+        let mut octrees = self.octrees.lock();
+        Some(f(octrees.entry(pos).or_insert_with(|| SparseVoxelOctree::from_chunk(chunk))))
+    }
+
+    // first solid block along a ray. steps chunk by chunk, and through each
+    // loaded chunk by the leaves of its octree rather than voxel by voxel
+    pub fn raycast(&self, ray: &Ray) -> VoxelRayResult {
+        let origin = ray.origin.to_glam();
+        let dir = ray.direction.to_glam();
+        let size = Chunk::SIZE as f32;
+
+        let mut chunk = (origin / size).floor().as_ivec3();
+        let step = IVec3::new(dir.x.signum() as i32, dir.y.signum() as i32, dir.z.signum() as i32);
+        let moving = dir.cmpne(Vec3::ZERO);
+        let t_delta = Vec3::select(moving, size / dir.abs(), Vec3::INFINITY);
+        let boundary = (chunk + step.max(IVec3::ZERO)).as_vec3() * size;
+        let mut t_max = Vec3::select(moving, (boundary - origin) / dir, Vec3::INFINITY);
+
+        let mut t = 0.0;
+        while t < ray.distance {
+            let pos = ChunkPos { x: chunk.x, y: chunk.y, z: chunk.z };
+            let local = origin - chunk.as_vec3() * size;
+            if let Some(Some(hit)) = self.with_octree(pos, |octree| octree.raycast(local, dir, ray.distance)) {
+                return Some(RaycastHit {
+                    position: Vec3f::from_glam(origin + dir * hit.t),
+                    normal: Vec3f::from_glam(hit.normal),
+                    distance: hit.t,
+                    voxel: hit.block,
+                });
+            }
+
+            let axis = if t_max.x < t_max.y && t_max.x < t_max.z {
+                0
+            } else if t_max.y < t_max.z {
+                1
+            } else {
+                2
+            };
+            t = t_max[axis];
+            chunk[axis] += step[axis];
+            t_max[axis] += t_delta[axis];
+        }
+
+        None
+    }
+
+    // positions of every loaded chunk
+    pub fn loaded_chunks(&self) -> Vec<ChunkPos> {
+        self.chunks.read().keys().copied().collect()
+    }
+
+    // greedy mesh of a loaded chunk, culled against whichever neighbours are loaded
+    pub fn mesh_chunk(&self, pos: ChunkPos) -> Option<MeshData> {
+        self.with_neighbours(pos, mesh_chunk)
+    }
+
+    // smooth isosurface of a loaded chunk's voxels
+    pub fn smooth_mesh_chunk(&self, pos: ChunkPos) -> Option<MeshData> {
+        self.with_neighbours(pos, |neighbours| dual_contour(&ChunkDensity::new(neighbours, pos), pos))
+    }
+
+    fn with_neighbours<R>(&self, pos: ChunkPos, f: impl FnOnce(&ChunkNeighbours) -> R) -> Option<R> {
+        let chunks = self.chunks.read();
+        let mut neighbours = ChunkNeighbours::new(chunks.get(&pos)?);
+        for dz in -1..=1 {
+            for dy in -1..=1 {
+                for dx in -1..=1 {
+                    let offset = ChunkPos { x: pos.x + dx, y: pos.y + dy, z: pos.z + dz };
+                    if (dx, dy, dz) != (0, 0, 0) {
+                        if let Some(chunk) = chunks.get(&offset) {
+                            neighbours.set(dx, dy, dz, chunk);
+                        }
+                    }
+                }
+            }
+        }
+        Some(f(&neighbours))
     }
 
     // update world state
@@ -64,8 +565,216 @@ impl World {
         // TODO: add dynamic world updates
     }
 
+    pub fn generator(&self) -> Arc<dyn TerrainGenerator> {
+        self.generate.read().clone()
+    }
+
+    // swap the generator. chunks already loaded keep their terrain, anything
+    // unloaded or not yet generated comes back from the new one
+    pub fn set_generator(&self, generator: impl TerrainGenerator + 'static) {
+        self.replace_generator(Arc::new(generator));
+    }
+
+    fn replace_generator(&self, generator: Arc<dyn TerrainGenerator>) {
+        let mut staging = self.staging.lock();
+        *self.generate.write() = generator;
+        // half-built chunks would mix the two generators' stages, and the old
+        // one's features would be replayed into chunks the new one builds
+        staging.proto.clear();
+        staging.pending.clear();
+        staging.epoch += 1;
+        self.staged.notify_all();
+    }
+
+    // settings the current generator was built from, saved with exported
+    // regions. `None` if it doesn't take a config
+    pub fn generator_config(&self) -> Option<GeneratorConfig> {
+        self.generate.read().config().cloned()
+    }
+
+    // rebuild the current generator with new settings, keeping its biomes,
+    // features and carver, see `set_generator`. fails for generators that
+    // don't take a config rather than swapping them out
+    pub fn apply_generator_config(&self, config: GeneratorConfig) -> Result<(), ConfigError> {
+        let generator = self.generator().reconfigure(config).ok_or(ConfigError::Unsupported)?;
+        self.replace_generator(Arc::from(generator));
+        Ok(())
+    }
+
+    // biome of a world column, `None` if the generator has no biomes
+    pub fn biome_at(&self, x: i32, z: i32) -> Option<Biome> {
+        self.generate.read().biome_at(x, z).cloned()
+    }
+
     // get world seed
     pub fn seed(&self) -> u32 {
         self.seed
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// heightmap terrain with veins and boulders dense enough to overlap
+    /// across chunk borders, where the order writes land in matters
+    fn crowded_world(seed: u32) -> World {
+        let rule = |feature, attempts| FeatureRule { feature, attempts, chance: 1.0, biomes: Vec::new(), max_y: i32::MAX };
+        let mut generator = WorldGenerator::new(seed);
+        generator.set_carver(None);
+        generator.set_features(FeaturePlacer::new(seed, vec![
+            rule(Feature::OreVein { block: block::COAL_ORE, size: 24 }, 24),
+            rule(Feature::OreVein { block: block::IRON_ORE, size: 24 }, 24),
+            rule(Feature::Boulder { radius: 2 }, 4),
+            rule(Feature::Tree { height: 5, crown: 2 }, 8),
+        ]));
+        World::with_generator(seed, generator)
+    }
+
+    fn area() -> Vec<ChunkPos> {
+        (0..2).flat_map(|z| (1..3).flat_map(move |y| (0..2).map(move |x| ChunkPos { x, y, z }))).collect()
+    }
+
+    fn blocks(world: &World, pos: ChunkPos) -> Vec<u8> {
+        world.with_chunk(pos, |chunk| chunk.blocks().to_vec()).expect("chunk should be loaded")
+    }
+
+    #[test]
+    fn generation_order_does_not_change_chunks() {
+        let forwards = crowded_world(7);
+        let backwards = crowded_world(7);
+        for pos in area() {
+            forwards.generate_chunk(pos);
+        }
+        for pos in area().into_iter().rev() {
+            backwards.generate_chunk(pos);
+        }
+        for pos in area() {
+            assert!(blocks(&forwards, pos) == blocks(&backwards, pos), "chunk {pos:?} differs");
+        }
+    }
+
+    #[test]
+    fn regenerated_chunk_matches_the_first_time() {
+        let world = crowded_world(3);
+        for pos in area() {
+            world.generate_chunk(pos);
+        }
+        let pos = area()[3];
+        let first = blocks(&world, pos);
+        world.unload_chunk(pos);
+        world.generate_chunk(pos);
+        assert!(first == blocks(&world, pos));
+    }
+
+    #[test]
+    fn threads_generate_the_same_chunks() {
+        let alone = crowded_world(11);
+        for pos in area() {
+            alone.generate_chunk(pos);
+        }
+        let shared = crowded_world(11);
+        std::thread::scope(|scope| {
+            for offset in 0..4 {
+                let shared = &shared;
+                scope.spawn(move || {
+                    for pos in area().into_iter().cycle().skip(offset * 2).take(area().len()) {
+                        shared.generate_chunk(pos);
+                    }
+                });
+            }
+        });
+        for pos in area() {
+            assert!(blocks(&alone, pos) == blocks(&shared, pos), "chunk {pos:?} differs");
+        }
+    }
+
+    #[test]
+    fn unloading_drops_the_staging_ring() {
+        let world = crowded_world(5);
+        let pos = ChunkPos { x: 0, y: 2, z: 0 };
+        world.generate_chunk(pos);
+        world.unload_chunk(pos);
+        assert!(pos.neighbours().all(|neighbour| world.chunk_status(neighbour) == ChunkStatus::Empty));
+    }
+
+    #[test]
+    fn only_full_chunks_are_loaded() {
+        let world = crowded_world(1);
+        let pos = ChunkPos { x: 0, y: 2, z: 0 };
+        world.generate_chunk(pos);
+        assert_eq!(world.loaded_chunks(), vec![pos]);
+        for neighbour in pos.neighbours() {
+            assert_eq!(world.chunk_status(neighbour), ChunkStatus::Features);
+        }
+    }
+
+    #[test]
+    fn exported_regions_carry_the_generator_config() {
+        let config = GeneratorConfig { sea_level: 50, amplitude: 30.0, ..Default::default() };
+        let world = World::with_config(3, config.clone());
+        let pos = ChunkPos { x: 0, y: 1, z: 0 };
+        world.generate_chunk(pos);
+
+        let mut bytes = Vec::new();
+        world.export_region(pos, pos).write_to(&mut bytes).unwrap();
+        let region = Region::read_from(&mut bytes.as_slice()).unwrap();
+
+        let loaded = World::new(3);
+        loaded.import_region(region.clone()).unwrap();
+        assert_eq!(loaded.generator_config(), Some(config));
+        assert_eq!(blocks(&loaded, pos), blocks(&world, pos));
+
+        let void = World::with_generator(3, VoidGenerator);
+        assert!(matches!(void.import_region(region), Err(ConfigError::Unsupported)));
+        assert!(!void.is_loaded(pos));
+    }
+
+    /// a stone roof over the top of every chunk at y = 1 west of x = 1
+    struct Roof;
+
+    impl TerrainGenerator for Roof {
+        fn generate_chunk(&self, chunk: &mut Chunk, pos: ChunkPos) {
+            if pos.y == 1 && pos.x <= 0 {
+                for z in 0..Chunk::SIZE {
+                    for x in 0..Chunk::SIZE {
+                        chunk.set_block(x, Chunk::SIZE - 1, z, block::STONE);
+                    }
+                }
+            }
+        }
+    }
+
+    fn light(world: &World, pos: ChunkPos, x: usize) -> Option<u8> {
+        world.with_chunk(pos, |chunk| chunk.sky_light(x, 10, 5)).flatten()
+    }
+
+    #[test]
+    fn light_crosses_side_faces() {
+        let (roofed, open) = (ChunkPos { x: 0, y: 1, z: 0 }, ChunkPos { x: 1, y: 1, z: 0 });
+        for order in [[roofed, open], [open, roofed]] {
+            let world = World::with_generator(0, Roof);
+            world.generate_chunk(order[0]);
+            world.generate_chunk(order[1]);
+            assert_eq!(light(&world, open, 0), Some(Chunk::MAX_LIGHT));
+            assert_eq!(light(&world, roofed, Chunk::SIZE - 1), Some(Chunk::MAX_LIGHT - 1));
+            assert_eq!(light(&world, roofed, Chunk::SIZE - 4), Some(Chunk::MAX_LIGHT - 4));
+            assert_eq!(light(&world, roofed, 0), Some(0));
+        }
+    }
+
+    #[test]
+    fn edits_relight_the_chunks_beside() {
+        let (roofed, open) = (ChunkPos { x: 0, y: 1, z: 0 }, ChunkPos { x: 1, y: 1, z: 0 });
+        let world = World::with_generator(0, Roof);
+        world.generate_chunk(roofed);
+        world.generate_chunk(open);
+        // a wall down the open chunk's west face shuts the light out again
+        for y in 0..Chunk::SIZE as i32 {
+            for z in 0..Chunk::SIZE as i32 {
+                world.set_block(Chunk::SIZE as i32, Chunk::SIZE as i32 + y, z, block::STONE);
+            }
+        }
+        assert_eq!(light(&world, roofed, Chunk::SIZE - 1), Some(0));
+    }
+}
@@ -0,0 +1,196 @@
+use glam::Vec3;
+use crate::utils::ray::{exit_times, face_normal};
+use super::Chunk;
+
+/// gpu node word: leaf rather than an index to children
+pub const OCTREE_LEAF: u32 = 1 << 31;
+/// gpu leaf word: 2³ node with mixed contents, read the voxels instead
+pub const OCTREE_MIXED: u32 = 1 << 30;
+
+/// nudge past a node boundary so the next lookup lands in the neighbour
+const EPSILON: f32 = 1e-4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OctreeNode {
+    /// uniform region of one block, air included
+    Leaf(u8),
+    /// index of the first of eight contiguous children, x fastest then y then z
+    Branch(u32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OctreeHit {
+    pub t: f32,
+    pub block: u8,
+    pub normal: Vec3,
+}
+
+/// sparse voxel octree over one chunk. uniform regions collapse into a single
+/// leaf, so rays cross large empty spaces in a handful of steps
+#[derive(Debug, Clone)]
+pub struct SparseVoxelOctree {
+    root: OctreeNode,
+    nodes: Vec<OctreeNode>,
+}
+
+impl SparseVoxelOctree {
+    /// words `encode_gpu` can produce at most: every node down to 2³ present
+    pub const GPU_WORDS: usize = 1 + 8 + 64 + 512 + 4096;
+
+    pub fn from_chunk(chunk: &Chunk) -> Self {
+        let mut nodes = Vec::new();
+        let root = Self::build(chunk, &mut nodes, [0; 3], Chunk::SIZE);
+        Self { root, nodes }
+    }
+
+    pub fn root(&self) -> OctreeNode {
+        self.root
+    }
+
+    pub fn nodes(&self) -> &[OctreeNode] {
+        &self.nodes
+    }
+
+    /// leaf holding a chunk-local voxel: its block, min corner and size
+    pub fn lookup(&self, x: usize, y: usize, z: usize) -> (u8, [usize; 3], usize) {
+        let mut node = self.root;
+        let mut min = [0; 3];
+        let mut size = Chunk::SIZE;
+
+        while let OctreeNode::Branch(base) = node {
+            size /= 2;
+            let mut child = 0;
+            for (axis, v) in [x, y, z].into_iter().enumerate() {
+                if v >= min[axis] + size {
+                    min[axis] += size;
+                    child |= 1 << axis;
+                }
+            }
+            node = self.nodes[base as usize + child];
+        }
+
+        let OctreeNode::Leaf(block) = node else { unreachable!() };
+        (block, min, size)
+    }
+
+    /// first solid voxel along a ray in chunk-local space, `t` in units of `dir`.
+    /// each step jumps to where the ray leaves the leaf it is in
+    pub fn raycast(&self, origin: Vec3, dir: Vec3, max_t: f32) -> Option<OctreeHit> {
+        let size = Chunk::SIZE as f32;
+        let (mut t, exit, mut normal) = clip(origin, dir, Vec3::ZERO, Vec3::splat(size))?;
+        let exit = exit.min(max_t);
+
+        while t < exit {
+            let p = (origin + dir * (t + EPSILON)).clamp(Vec3::ZERO, Vec3::splat(size - 1.0));
+            let (block, min, leaf_size) = self.lookup(p.x as usize, p.y as usize, p.z as usize);
+            if block != 0 {
+                return Some(OctreeHit { t, block, normal });
+            }
+
+            let min = Vec3::new(min[0] as f32, min[1] as f32, min[2] as f32);
+            let exits = exit_times(origin, dir, min, leaf_size as f32);
+            // entering the next leaf through the face on whichever axis was left first
+            normal = face_normal(-exits, dir);
+            t = exits.min_element().max(t + EPSILON);
+        }
+
+        None
+    }
+
+    /// flat encoding for the shader, root at word 0. a word with `OCTREE_LEAF`
+    /// set holds a block in its low byte; otherwise it is the index of eight
+    /// child words. 2³ nodes that aren't uniform become `OCTREE_MIXED` leaves
+    /// since the shader has the voxels themselves for that
+    pub fn encode_gpu(&self) -> Vec<u32> {
+        let mut words = vec![0];
+        words[0] = self.encode_node(self.root, Chunk::SIZE, &mut words);
+        words
+    }
+
+    fn encode_node(&self, node: OctreeNode, size: usize, words: &mut Vec<u32>) -> u32 {
+        match node {
+            OctreeNode::Leaf(block) => OCTREE_LEAF | block as u32,
+            OctreeNode::Branch(_) if size == 2 => OCTREE_LEAF | OCTREE_MIXED,
+            OctreeNode::Branch(base) => {
+                let children: [u32; 8] = std::array::from_fn(|i| {
+                    self.encode_node(self.nodes[base as usize + i], size / 2, words)
+                });
+                let index = words.len() as u32;
+                words.extend(children);
+                index
+            }
+        }
+    }
+
+    fn build(chunk: &Chunk, nodes: &mut Vec<OctreeNode>, min: [usize; 3], size: usize) -> OctreeNode {
+        if size == 1 {
+            return OctreeNode::Leaf(chunk.get_block(min[0], min[1], min[2]));
+        }
+
+        let half = size / 2;
+        let children: [OctreeNode; 8] = std::array::from_fn(|i| {
+            let child = [min[0] + (i & 1) * half, min[1] + ((i >> 1) & 1) * half, min[2] + (i >> 2) * half];
+            Self::build(chunk, nodes, child, half)
+        });
+
+        if let OctreeNode::Leaf(block) = children[0] {
+            if children.iter().all(|&c| c == OctreeNode::Leaf(block)) {
+                return children[0];
+            }
+        }
+
+        let base = nodes.len() as u32;
+        nodes.extend(children);
+        OctreeNode::Branch(base)
+    }
+}
+
+/// slab test: entry t, exit t and the face normal at entry
+fn clip(origin: Vec3, dir: Vec3, min: Vec3, max: Vec3) -> Option<(f32, f32, Vec3)> {
+    let inv = dir.recip();
+    let t0 = (min - origin) * inv;
+    let t1 = (max - origin) * inv;
+    let near = t0.min(t1);
+    let far = t0.max(t1);
+
+    let enter = near.max_element().max(0.0);
+    let exit = far.min_element();
+    if enter > exit {
+        return None;
+    }
+
+    let normal = if near.max_element() <= 0.0 {
+        Vec3::ZERO
+    } else if near.x >= near.y && near.x >= near.z {
+        Vec3::new(-dir.x.signum(), 0.0, 0.0)
+    } else if near.y >= near.z {
+        Vec3::new(0.0, -dir.y.signum(), 0.0)
+    } else {
+        Vec3::new(0.0, 0.0, -dir.z.signum())
+    };
+    Some((enter, exit, normal))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn axis_aligned_rays_hit_the_facing_side() {
+        let mut chunk = Chunk::new();
+        chunk.set_block(5, 0, 5, 1);
+        chunk.set_block(20, 9, 3, 2);
+        let octree = SparseVoxelOctree::from_chunk(&chunk);
+
+        let hit = octree.raycast(Vec3::new(5.5, 31.5, 5.5), Vec3::NEG_Y, 100.0).expect("ray should hit the block");
+        assert_eq!((hit.block, hit.normal), (1, Vec3::Y));
+        assert!((hit.t - 30.5).abs() < 1e-3);
+
+        let hit = octree.raycast(Vec3::new(0.5, 9.5, 3.5), Vec3::X, 100.0).expect("ray should hit the block");
+        assert_eq!((hit.block, hit.normal), (2, Vec3::NEG_X));
+        assert!((hit.t - 19.5).abs() < 1e-3);
+
+        let hit = octree.raycast(Vec3::new(20.5, 9.5, 31.5), Vec3::NEG_Z, 100.0).expect("ray should hit the block");
+        assert_eq!((hit.block, hit.normal), (2, Vec3::Z));
+    }
+}
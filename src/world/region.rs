@@ -0,0 +1,182 @@
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use super::{Chunk, ChunkPos, GeneratorConfig};
+
+const MAGIC: &[u8; 4] = b"HCRG";
+const VERSION: u32 = 2;
+
+/// a set of chunks cut out of a world, saved and loaded as one file along
+/// with the settings of the generator that made them.
+///
+/// layout, little endian: magic, version, the config's toml length and text
+/// (length 0 for none), chunk count, then per chunk its position as three i32
+/// followed by `Chunk::VOLUME` block ids. version 1 files have no config
+#[derive(Debug, Clone, Default)]
+pub struct Region {
+    chunks: HashMap<ChunkPos, Chunk>,
+    config: Option<GeneratorConfig>,
+}
+
+impl Region {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, pos: ChunkPos, chunk: Chunk) {
+        self.chunks.insert(pos, chunk);
+    }
+
+    pub fn get(&self, pos: ChunkPos) -> Option<&Chunk> {
+        self.chunks.get(&pos)
+    }
+
+    pub fn len(&self) -> usize {
+        self.chunks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    /// settings of the generator the chunks came from, applied to the world
+    /// they are imported into
+    pub fn config(&self) -> Option<&GeneratorConfig> {
+        self.config.as_ref()
+    }
+
+    pub fn set_config(&mut self, config: Option<GeneratorConfig>) {
+        self.config = config;
+    }
+
+    pub fn chunks(&self) -> impl Iterator<Item = (ChunkPos, &Chunk)> + '_ {
+        self.chunks.iter().map(|(pos, chunk)| (*pos, chunk))
+    }
+
+    pub fn into_chunks(self) -> impl Iterator<Item = (ChunkPos, Chunk)> {
+        self.chunks.into_iter()
+    }
+
+    /// smallest and largest chunk position held, `None` when empty
+    pub fn bounds(&self) -> Option<(ChunkPos, ChunkPos)> {
+        let mut positions = self.chunks.keys();
+        let first = *positions.next()?;
+        Some(positions.fold((first, first), |(min, max), pos| (
+            ChunkPos { x: min.x.min(pos.x), y: min.y.min(pos.y), z: min.z.min(pos.z) },
+            ChunkPos { x: max.x.max(pos.x), y: max.y.max(pos.y), z: max.z.max(pos.z) },
+        )))
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut file = io::BufWriter::new(std::fs::File::create(path)?);
+        self.write_to(&mut file)?;
+        file.flush()
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::read_from(&mut io::BufReader::new(std::fs::File::open(path)?))
+    }
+
+    pub fn write_to(&self, out: &mut impl Write) -> io::Result<()> {
+        out.write_all(MAGIC)?;
+        out.write_all(&VERSION.to_le_bytes())?;
+        let config = match &self.config {
+            Some(config) => config.to_toml().map_err(io::Error::other)?,
+            None => String::new(),
+        };
+        out.write_all(&(config.len() as u32).to_le_bytes())?;
+        out.write_all(config.as_bytes())?;
+        out.write_all(&(self.chunks.len() as u32).to_le_bytes())?;
+        for (pos, chunk) in &self.chunks {
+            for v in [pos.x, pos.y, pos.z] {
+                out.write_all(&v.to_le_bytes())?;
+            }
+            out.write_all(chunk.blocks())?;
+        }
+        Ok(())
+    }
+
+    pub fn read_from(input: &mut impl Read) -> io::Result<Self> {
+        let mut magic = [0; 4];
+        input.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "not a region file"));
+        }
+        let version = read_u32(input)?;
+        if version != 1 && version != VERSION {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("unsupported region version {version}")));
+        }
+
+        let mut region = Self::new();
+        if version >= 2 {
+            let len = read_u32(input)? as u64;
+            let mut text = String::new();
+            input.by_ref().take(len).read_to_string(&mut text)?;
+            if text.len() as u64 != len {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            if len > 0 {
+                let config = GeneratorConfig::from_toml(&text)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                region.config = Some(config);
+            }
+        }
+
+        let count = read_u32(input)?;
+        let mut blocks = vec![0; Chunk::VOLUME];
+        for _ in 0..count {
+            let [x, y, z] = [read_u32(input)?, read_u32(input)?, read_u32(input)?].map(|v| v as i32);
+            input.read_exact(&mut blocks)?;
+            region.insert(ChunkPos { x, y, z }, Chunk::from_blocks(&blocks));
+        }
+        Ok(region)
+    }
+}
+
+pub(crate) fn read_u32(input: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    input.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn region(config: Option<GeneratorConfig>) -> Region {
+        let mut chunk = Chunk::new();
+        chunk.set_block(1, 2, 3, 4);
+        let mut region = Region::new();
+        region.insert(ChunkPos { x: -2, y: 0, z: 7 }, chunk);
+        region.set_config(config);
+        region
+    }
+
+    fn round_trip(region: &Region) -> Region {
+        let mut bytes = Vec::new();
+        region.write_to(&mut bytes).unwrap();
+        Region::read_from(&mut bytes.as_slice()).unwrap()
+    }
+
+    #[test]
+    fn config_round_trips() {
+        let config = GeneratorConfig { sea_level: 40, octaves: 6, ..Default::default() };
+        let read = round_trip(&region(Some(config.clone())));
+        assert_eq!(read.config(), Some(&config));
+        assert_eq!(read.get(ChunkPos { x: -2, y: 0, z: 7 }).unwrap().get_block(1, 2, 3), 4);
+
+        assert_eq!(round_trip(&region(None)).config(), None);
+    }
+
+    #[test]
+    fn version_one_files_load_without_a_config() {
+        let mut bytes = MAGIC.to_vec();
+        for v in [1u32, 1, -2i32 as u32, 0, 7] {
+            bytes.extend_from_slice(&v.to_le_bytes());
+        }
+        bytes.extend(std::iter::repeat_n(0, Chunk::VOLUME));
+        let read = Region::read_from(&mut bytes.as_slice()).unwrap();
+        assert_eq!(read.len(), 1);
+        assert_eq!(read.config(), None);
+    }
+}